@@ -0,0 +1,68 @@
+use crate::data_processing::Product;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Normalized ASIN -> position in the product list. Graph builders add one node per
+// product in list order, so the position doubles as the node index.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AsinIndex {
+    positions: HashMap<String, usize>,
+}
+
+impl AsinIndex {
+    pub fn normalize(asin: &str) -> String {
+        asin.trim().to_lowercase()
+    }
+
+    pub fn build(products: &[Product]) -> Self {
+        let mut index = AsinIndex {
+            positions: HashMap::with_capacity(products.len()),
+        };
+        for (position, product) in products.iter().enumerate() {
+            if let Some(asin) = &product.asin {
+                index.insert(asin, position);
+            }
+        }
+        index
+    }
+
+    // The first product with a given ASIN wins, like the old linear `find`
+    pub fn insert(&mut self, asin: &str, position: usize) {
+        self.positions.entry(Self::normalize(asin)).or_insert(position);
+    }
+
+    pub fn position(&self, asin: &str) -> Option<usize> {
+        self.positions.get(&Self::normalize(asin)).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{product, sample_products};
+
+    #[test]
+    fn test_lookups_are_normalized() {
+        let index = AsinIndex::build(&sample_products());
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.position(" 0738700797 "), Some(2));
+        assert_eq!(index.position("0804215715"), None);
+    }
+
+    #[test]
+    fn test_first_product_with_an_asin_wins() {
+        let mut duplicate = product(0);
+        duplicate.asin = duplicate.asin.map(|asin| asin.to_lowercase());
+        let index = AsinIndex::build(&[product(0), duplicate]);
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.position("A000000000"), Some(0));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::data_processing::Product;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+// One node of a SNAP category path such as `Religion & Spirituality[22]`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Category {
+    pub name: String,
+    pub id: u32,
+}
+
+impl Category {
+    // Parse a single `Name[id]` segment; segments without an id are skipped
+    pub fn parse_segment(segment: &str) -> Option<Category> {
+        let segment = segment.trim();
+        let open = segment.rfind('[')?;
+        let id = segment[open + 1..].strip_suffix(']')?.parse().ok()?;
+        Some(Category {
+            name: segment[..open].trim().to_string(),
+            id,
+        })
+    }
+
+    // `Name[id]`, unique even where names repeat across branches
+    pub fn label(&self) -> String {
+        format!("{}[{}]", self.name, self.id)
+    }
+
+    // Parse a full `|Books[283155]|Subjects[1000]|...` line, root first
+    pub fn parse_path(line: &str) -> Vec<Category> {
+        line.split('|').filter_map(Category::parse_segment).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryNode {
+    pub category: Category,
+    pub parent: Option<u32>,
+    pub children: BTreeSet<u32>,
+    pub depth: usize,
+    // Distinct products with at least one path through this category
+    pub product_count: usize,
+    // Distinct products with a path ending at this category
+    pub leaf_product_count: usize,
+}
+
+// The category hierarchy shared by all products, keyed by SNAP category id
+#[derive(Debug, Clone, Default)]
+pub struct CategoryTree {
+    nodes: HashMap<u32, CategoryNode>,
+    roots: BTreeSet<u32>,
+}
+
+impl CategoryTree {
+    pub fn from_products<'a, I>(products: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Product>,
+    {
+        let mut tree = CategoryTree::default();
+        for product in products {
+            tree.add_product(product);
+        }
+        tree
+    }
+
+    pub fn add_product(&mut self, product: &Product) {
+        let mut seen = HashSet::new();
+        let mut leaves = HashSet::new();
+
+        for path in product.category_paths() {
+            let mut parent = None;
+            for (depth, category) in path.iter().enumerate() {
+                let node = self.nodes.entry(category.id).or_insert_with(|| CategoryNode {
+                    category: category.clone(),
+                    parent,
+                    children: BTreeSet::new(),
+                    depth,
+                    product_count: 0,
+                    leaf_product_count: 0,
+                });
+                // The first parent seen wins if the dump lists a category under two parents
+                let node_parent = node.parent;
+                if seen.insert(category.id) {
+                    node.product_count += 1;
+                }
+                match node_parent {
+                    Some(parent_id) => {
+                        if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
+                            parent_node.children.insert(category.id);
+                        }
+                    }
+                    None => {
+                        self.roots.insert(category.id);
+                    }
+                }
+                parent = Some(category.id);
+            }
+            if let Some(leaf) = path.last() {
+                if leaves.insert(leaf.id) {
+                    if let Some(node) = self.nodes.get_mut(&leaf.id) {
+                        node.leaf_product_count += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: u32) -> Option<&CategoryNode> {
+        self.nodes.get(&id)
+    }
+
+    pub fn roots(&self) -> Vec<&Category> {
+        self.roots.iter().filter_map(|id| self.nodes.get(id)).map(|n| &n.category).collect()
+    }
+
+    pub fn product_count(&self, id: u32) -> usize {
+        self.nodes.get(&id).map_or(0, |n| n.product_count)
+    }
+
+    // Ancestors of a category, root first, not including the category itself
+    pub fn ancestors(&self, id: u32) -> Vec<&Category> {
+        let mut ancestors = Vec::new();
+        let mut current = self.nodes.get(&id).and_then(|n| n.parent);
+        while let Some(parent_id) = current {
+            match self.nodes.get(&parent_id) {
+                Some(node) => {
+                    ancestors.push(&node.category);
+                    current = node.parent;
+                }
+                None => break,
+            }
+        }
+        ancestors.reverse();
+        ancestors
+    }
+
+    // All categories below `id`, breadth first
+    pub fn descendants(&self, id: u32) -> Vec<&Category> {
+        let mut descendants = Vec::new();
+        let mut queue: VecDeque<u32> = self
+            .nodes
+            .get(&id)
+            .map(|n| n.children.iter().copied().collect())
+            .unwrap_or_default();
+        while let Some(child_id) = queue.pop_front() {
+            if let Some(node) = self.nodes.get(&child_id) {
+                descendants.push(&node.category);
+                queue.extend(node.children.iter().copied());
+            }
+        }
+        descendants
+    }
+
+    pub fn categories_at_depth(&self, depth: usize) -> Vec<&Category> {
+        let mut categories: Vec<_> = self
+            .nodes
+            .values()
+            .filter(|n| n.depth == depth)
+            .map(|n| &n.category)
+            .collect();
+        categories.sort();
+        categories
+    }
+}
+
+// Group products by their category at `depth` (0 is the root, e.g. `Books`).
+// A product with several paths can appear in more than one group.
+pub fn group_products_at_depth<'a, I>(products: I, depth: usize) -> HashMap<Category, Vec<&'a Product>>
+where
+    I: IntoIterator<Item = &'a Product>,
+{
+    let mut groups: HashMap<Category, Vec<&Product>> = HashMap::new();
+    for product in products {
+        let categories: BTreeSet<Category> = product
+            .category_paths()
+            .into_iter()
+            .filter_map(|mut path| (path.len() > depth).then(|| path.swap_remove(depth)))
+            .collect();
+        for category in categories {
+            groups.entry(category).or_default().push(product);
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::sample_cleaner;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            Category::parse_path("|Books[283155]|Subjects[1000]|Religion & Spirituality[22]"),
+            vec![
+                Category { name: "Books".to_string(), id: 283155 },
+                Category { name: "Subjects".to_string(), id: 1000 },
+                Category { name: "Religion & Spirituality".to_string(), id: 22 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tree_counts_products() {
+        let tree = sample_cleaner().category_tree();
+        assert_eq!(tree.roots().len(), 1);
+        assert_eq!(tree.product_count(283155), 2);
+        assert_eq!(tree.product_count(12290), 1); // Christianity
+        assert_eq!(tree.get(12360).unwrap().leaf_product_count, 0);
+        assert_eq!(tree.get(12368).unwrap().leaf_product_count, 1);
+    }
+
+    #[test]
+    fn test_tree_ancestors_and_descendants() {
+        let tree = sample_cleaner().category_tree();
+        let ancestors: Vec<u32> = tree.ancestors(12368).iter().map(|c| c.id).collect();
+        assert_eq!(ancestors, vec![283155, 1000, 22, 12290, 12360]);
+
+        let descendants: Vec<u32> = tree.descendants(22).iter().map(|c| c.id).collect();
+        assert_eq!(descendants, vec![12290, 12472, 12360, 12484, 12368, 12370]);
+    }
+}
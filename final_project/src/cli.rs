@@ -0,0 +1,1561 @@
+use crate::community::{detect_communities, CommunityDetection, CommunityMethod};
+use crate::data_analysis::{
+    analyze_components, calculate_clustering, calculate_degree_centrality, calculate_distances,
+    calculate_eigenvector_centrality, calculate_hits, calculate_mixing_matrix, calculate_path_centrality,
+    calculate_personalized_pagerank, extract_giant_component, seed_nodes_by_asin, seed_nodes_by_group,
+    top_k_scores, AmazonDataAnalysis, CategoryCentrality, CategoryComponents, CategoryDistances,
+    ComponentAnalysis, Connectivity, CoPurchaseRatio, Convergence, DegreeCentrality, DistanceMode,
+    DistanceSummary, MixingMatrix, NodePathCentrality, PageRankOptions, PathCentrality, PathCentralityMode,
+    PowerIterationOptions,
+};
+use crate::data_processing::{
+    AmazonDataCleaner, CategorySummary, DanglingPolicy, GroupBy, ParseMode, ParseReport, RankBy, RankedProduct,
+    StatisticsRow, SummaryOptions,
+};
+use crate::output::{write_json, write_records, OutputFormat, Provenance};
+use crate::product_graph::{EdgeDirection, ProductGraph, ProductNode};
+use crate::sampling::{random_seed, SamplingStrategy};
+use crate::statistics::DescriptiveStats;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Co-purchase network analysis of the SNAP Amazon product metadata
+#[derive(Debug, Parser)]
+#[command(name = "final_project", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Parse and validate the metadata file, then refresh the snapshot cache
+    Parse {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Stop at the first malformed field instead of reporting warnings
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Draw a reproducible sample and write the sampled products
+    Sample {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        #[command(flatten)]
+        strategy: StrategyArgs,
+    },
+    /// Summarize the largest product groups
+    Summarize {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        #[command(flatten)]
+        grouping: GroupingArgs,
+        /// Full distributions (quartiles, spread, missing counts) instead of means
+        #[arg(long)]
+        detailed: bool,
+        /// Count salesranks filled in for products without one in the statistics
+        #[arg(long, requires = "detailed")]
+        include_imputed: bool,
+    },
+    /// Build the global co-purchase graph and export it
+    Graph {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        /// Keep similar ASINs outside the data as placeholder nodes
+        #[arg(long)]
+        external_nodes: bool,
+        /// Export only the largest weakly or strongly connected component
+        #[arg(long, value_enum, value_name = "CONNECTIVITY")]
+        giant_component: Option<ConnectivityArg>,
+    },
+    /// Centrality measures: per-group averages or per-product scores on the global graph
+    Centrality {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        #[command(flatten)]
+        grouping: GroupingArgs,
+        #[command(flatten)]
+        centrality: CentralityArgs,
+    },
+    /// Whether co-purchased products have similar degrees, salesranks and ratings
+    Assortativity {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        /// Write the average nearest-neighbour degree table instead of the coefficients
+        #[arg(long)]
+        neighbor_degree: bool,
+    },
+    /// Connected components of the global graph and of each top group's graph
+    Components {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        #[command(flatten)]
+        grouping: GroupingArgs,
+        #[arg(long, value_enum, default_value_t = ConnectivityArg::Weak)]
+        connectivity: ConnectivityArg,
+    },
+    /// Communities of the global graph compared with the product groups
+    Communities {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        /// Number of largest communities listed, 0 for all of them
+        #[arg(long, default_value_t = 3)]
+        top_n: usize,
+        #[arg(long, value_enum, default_value_t = CommunityMethodArg::Louvain)]
+        method: CommunityMethodArg,
+    },
+    /// Shortest path between two products, or diameters and average path lengths
+    Paths {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        #[command(flatten)]
+        grouping: GroupingArgs,
+        #[command(flatten)]
+        paths: PathArgs,
+    },
+    /// Triangles and clustering coefficients of the global graph, overall and per group
+    Clustering(AnalysisArgs),
+    /// In-group and cross-group co-purchase ratios, optionally with error bars and tests
+    Copurchase {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        #[command(flatten)]
+        significance: SignificanceArgs,
+    },
+    /// Co-purchase counts between every pair of groups (or categories with --category-depth)
+    Mixing {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        /// Number of most common targets listed for each label, 0 for all of them
+        #[arg(long, default_value_t = 3)]
+        top_n: usize,
+        /// Label by the category at this depth of the category paths instead of by product group
+        #[arg(long)]
+        category_depth: Option<usize>,
+        /// Also list label pairs without co-purchases, with their expected count
+        #[arg(long)]
+        unobserved: bool,
+    },
+    /// Run the whole pipeline: summary, centrality and co-purchase ratios
+    Report {
+        #[command(flatten)]
+        args: AnalysisArgs,
+        #[command(flatten)]
+        grouping: GroupingArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct InputArgs {
+    /// SNAP amazon-meta.txt file
+    #[arg(short, long, default_value = "amazon-meta.txt")]
+    pub input: String,
+    /// Snapshot cache of the parsed and cleaned data
+    #[arg(long, default_value = "amazon-meta.snapshot")]
+    pub snapshot: String,
+    /// Always parse the input and don't write a snapshot
+    #[arg(long)]
+    pub no_cache: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalysisArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Sampling seed; a random one is chosen and reported when omitted
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Number of products to sample, 0 for the whole data set
+    #[arg(long, default_value_t = 100000)]
+    pub sample_size: usize,
+    #[arg(long, value_enum, default_value_t = OutputFormatArg::Text)]
+    pub format: OutputFormatArg,
+    /// Write results here instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+// Which groups the per-group analyses cover; only commands with per-group output take these
+#[derive(Debug, Args)]
+pub struct GroupingArgs {
+    /// Number of groups in summaries and per-group analyses, 0 for all of them
+    #[arg(long, default_value_t = 3)]
+    pub top_n: usize,
+    /// How groups are ranked before taking the top N
+    #[arg(long, value_enum, default_value_t = RankByArg::Count)]
+    pub rank_by: RankByArg,
+    /// Group by the category at this depth of the category paths instead of by product group
+    #[arg(long)]
+    pub category_depth: Option<usize>,
+}
+
+impl GroupingArgs {
+    pub fn summary_options(&self) -> SummaryOptions {
+        SummaryOptions {
+            group_by: group_by(self.category_depth),
+            rank_by: self.rank_by.into(),
+            top_n: (self.top_n > 0).then_some(self.top_n),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CentralityArgs {
+    #[arg(long, value_enum, default_value_t = CentralityMeasure::Average)]
+    pub measure: CentralityMeasure,
+    /// Number of top products listed for each score
+    #[arg(long, default_value_t = 10)]
+    pub top_k: usize,
+    /// Divide degrees by (n - 1)
+    #[arg(long)]
+    pub normalize: bool,
+    /// PageRank damping factor
+    #[arg(long, default_value_t = 0.85)]
+    pub damping: f64,
+    /// Stop iterating once the total score change falls below this
+    #[arg(long, default_value_t = 1e-10)]
+    pub tolerance: f64,
+    /// Iteration cap for PageRank, eigenvector and HITS
+    #[arg(long, default_value_t = 100)]
+    pub max_iterations: usize,
+    /// Personalize PageRank towards this ASIN (repeatable)
+    #[arg(long = "personalize-asin", value_name = "ASIN")]
+    pub personalize_asins: Vec<String>,
+    /// Personalize PageRank towards every product in this group
+    #[arg(long, value_name = "GROUP")]
+    pub personalize_group: Option<String>,
+    /// Error bound for path-based measures, which are approximated from sampled pivots
+    #[arg(long, default_value_t = 0.1)]
+    pub epsilon: f64,
+    /// Probability that an approximate score misses the error bound
+    #[arg(long, default_value_t = 0.1)]
+    pub delta: f64,
+    /// Run Brandes from every product instead of sampling pivots; slow on the full graph
+    #[arg(long, conflicts_with_all = ["epsilon", "delta"])]
+    pub exact: bool,
+}
+
+impl CentralityArgs {
+    pub fn pagerank_options(&self) -> PageRankOptions {
+        PageRankOptions {
+            damping: self.damping,
+            tolerance: self.tolerance,
+            max_iterations: self.max_iterations,
+        }
+    }
+
+    pub fn power_iteration_options(&self) -> PowerIterationOptions {
+        PowerIterationOptions {
+            tolerance: self.tolerance,
+            max_iterations: self.max_iterations,
+        }
+    }
+
+    // The global graph is too large for exact Brandes, so pivots are the default
+    pub fn path_centrality_mode(&self, seed: u64) -> PathCentralityMode {
+        if self.exact {
+            PathCentralityMode::Exact
+        } else {
+            PathCentralityMode::Approximate {
+                epsilon: self.epsilon,
+                delta: self.delta,
+                seed,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct PathArgs {
+    /// ASIN the path starts from
+    #[arg(long, requires = "to")]
+    pub from: Option<String>,
+    /// ASIN the path ends at
+    #[arg(long, requires = "from")]
+    pub to: Option<String>,
+    /// Treat similar links as two-way
+    #[arg(long)]
+    pub undirected: bool,
+    /// Run a BFS from every product of the global graph instead of estimating
+    #[arg(long, conflicts_with = "approximate")]
+    pub exact: bool,
+    /// Estimate distances on the per-category graphs too
+    #[arg(long)]
+    pub approximate: bool,
+    /// Double sweeps for the approximate diameter
+    #[arg(long, default_value_t = 10)]
+    pub sweeps: usize,
+    /// BFS sources for the approximate average path length
+    #[arg(long, default_value_t = 100)]
+    pub sources: usize,
+}
+
+impl PathArgs {
+    // The global graph is too large for a BFS from every product, so it is
+    // estimated unless --exact is given; the per-category graphs are exact
+    // unless --approximate is given
+    pub fn distance_modes(&self, seed: u64) -> (DistanceMode, DistanceMode) {
+        let approximate = DistanceMode::Approximate {
+            sweeps: self.sweeps,
+            sources: self.sources,
+            seed,
+        };
+        let global = if self.exact { DistanceMode::Exact } else { approximate };
+        let categories = if self.approximate { approximate } else { DistanceMode::Exact };
+        (global, categories)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct StrategyArgs {
+    /// Sample with this strategy instead of uniformly at random
+    #[arg(long, value_enum)]
+    pub strategy: Option<StrategyArg>,
+    /// Start the snowball from this ASIN (repeatable); random products when omitted
+    #[arg(long = "seed-asin", value_name = "ASIN")]
+    pub seed_asins: Vec<String>,
+    /// Chance that the random walk jumps back to its start
+    #[arg(long, default_value_t = 0.15)]
+    pub restart_probability: f64,
+    /// Forest fire burn probability, below 1
+    #[arg(long, default_value_t = 0.7)]
+    pub forward_probability: f64,
+}
+
+impl StrategyArgs {
+    pub fn sampling_strategy(&self, strategy: StrategyArg, sample_size: usize) -> SamplingStrategy {
+        match strategy {
+            StrategyArg::UniformNode => SamplingStrategy::UniformNode { sample_size },
+            StrategyArg::InducedEdge => SamplingStrategy::InducedEdge { sample_size },
+            StrategyArg::Snowball => SamplingStrategy::Snowball {
+                seeds: self.seed_asins.clone(),
+                sample_size,
+            },
+            StrategyArg::RandomWalk => SamplingStrategy::RandomWalkWithRestart {
+                sample_size,
+                restart_probability: self.restart_probability,
+            },
+            StrategyArg::ForestFire => SamplingStrategy::ForestFire {
+                sample_size,
+                forward_probability: self.forward_probability,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SignificanceArgs {
+    /// Intervals from this many subsamples of --sample-size products, drawn without
+    /// replacement; they describe ratios at that sample size, which must be smaller than the data
+    #[arg(long, default_value_t = 0)]
+    pub subsamples: usize,
+    /// Coverage of the subsampling intervals, strictly between 0 and 1
+    #[arg(long, default_value_t = 0.95, value_parser = parse_open_unit)]
+    pub confidence: f64,
+    /// Test in-group preference against this many shuffles of the group labels
+    #[arg(long, default_value_t = 0)]
+    pub permutations: usize,
+}
+
+// A number strictly between 0 and 1
+fn parse_open_unit(value: &str) -> Result<f64, String> {
+    let number: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if number > 0.0 && number < 1.0 {
+        Ok(number)
+    } else {
+        Err(format!("{} is not strictly between 0 and 1", value))
+    }
+}
+
+// Library enums stay free of clap; these mirror them on the command line
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RankByArg {
+    /// Most products first
+    Count,
+    /// Best-selling (lowest mean salesrank) first
+    Salesrank,
+    /// Highest mean review rating first
+    Rating,
+    /// Most reviews first
+    Reviews,
+    /// Highest mean out-degree first
+    Degree,
+}
+
+impl From<RankByArg> for RankBy {
+    fn from(arg: RankByArg) -> Self {
+        match arg {
+            RankByArg::Count => RankBy::ProductCount,
+            RankByArg::Salesrank => RankBy::MeanSalesrank,
+            RankByArg::Rating => RankBy::MeanRating,
+            RankByArg::Reviews => RankBy::ReviewVolume,
+            RankByArg::Degree => RankBy::GraphDegree,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConnectivityArg {
+    /// Connected when linked either way
+    Weak,
+    /// Connected when each can reach the other
+    Strong,
+}
+
+impl From<ConnectivityArg> for Connectivity {
+    fn from(arg: ConnectivityArg) -> Self {
+        match arg {
+            ConnectivityArg::Weak => Connectivity::Weak,
+            ConnectivityArg::Strong => Connectivity::Strong,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CommunityMethodArg {
+    /// Greedy modularity optimisation
+    Louvain,
+    /// Most common label among neighbours
+    LabelPropagation,
+}
+
+impl From<CommunityMethodArg> for CommunityMethod {
+    fn from(arg: CommunityMethodArg) -> Self {
+        match arg {
+            CommunityMethodArg::Louvain => CommunityMethod::Louvain,
+            CommunityMethodArg::LabelPropagation => CommunityMethod::LabelPropagation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormatArg {
+    /// Human-readable tables
+    Text,
+    /// One JSON document with the sample seed and size
+    Json,
+    /// One JSON record per line
+    #[value(alias = "jsonl")]
+    JsonLines,
+    /// Comma-separated records with a header row
+    Csv,
+}
+
+impl From<OutputFormatArg> for OutputFormat {
+    fn from(arg: OutputFormatArg) -> Self {
+        match arg {
+            OutputFormatArg::Text => OutputFormat::Text,
+            OutputFormatArg::Json => OutputFormat::Json,
+            OutputFormatArg::JsonLines => OutputFormat::JsonLines,
+            OutputFormatArg::Csv => OutputFormat::Csv,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StrategyArg {
+    /// Products chosen uniformly at random
+    UniformNode,
+    /// Random similar links, keeping both ends
+    InducedEdge,
+    /// Breadth-first search from --seed-asin
+    Snowball,
+    /// Random walk with restarts
+    RandomWalk,
+    /// Forest fire along similar links
+    ForestFire,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CentralityMeasure {
+    /// Average degree of each top group's graph
+    Average,
+    /// In-, out- and total degree of every product
+    Degree,
+    /// PageRank, optionally personalized, of every product
+    Pagerank,
+    /// Share of shortest paths passing through each product
+    Betweenness,
+    /// Inverse mean distance to the products each one reaches
+    Closeness,
+    /// Mean inverse distance to every other product
+    Harmonic,
+    /// Eigenvector centrality along similar links
+    Eigenvector,
+    /// HITS hub and authority scores
+    Hits,
+}
+
+#[derive(Serialize)]
+struct GraphExport<'a> {
+    nodes: Vec<&'a ProductNode>,
+    edges: Vec<EdgeRow<'a>>,
+}
+
+// One graph edge as a flat row, so it can also be written as CSV
+#[derive(Serialize)]
+struct EdgeRow<'a> {
+    source: usize,
+    target: usize,
+    source_asin: Option<&'a str>,
+    target_asin: Option<&'a str>,
+    direction: EdgeDirection,
+    rank: usize,
+    reciprocated: bool,
+}
+
+// The sampled data an analysis runs on, with the seed that produced it
+struct Sample {
+    seed: u64,
+    cleaner: AmazonDataCleaner,
+}
+
+impl Sample {
+    fn provenance(&self) -> Provenance {
+        Provenance {
+            seed: self.seed,
+            sample_size: self.cleaner.data.len(),
+        }
+    }
+}
+
+pub fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Parse { input, strict } => run_parse(&input, strict),
+        Command::Sample { args, strategy } => run_sample(&args, &strategy),
+        Command::Summarize { args, grouping, detailed, include_imputed } => {
+            if detailed {
+                run_describe(&args, &grouping, include_imputed)
+            } else {
+                run_summarize(&args, &grouping)
+            }
+        }
+        Command::Graph { args, external_nodes, giant_component } => {
+            run_graph(&args, external_nodes, giant_component.map(Connectivity::from))
+        }
+        Command::Centrality { args, grouping, centrality } => run_centrality(&args, &grouping, &centrality),
+        Command::Assortativity { args, neighbor_degree } => run_assortativity(&args, neighbor_degree),
+        Command::Components { args, grouping, connectivity } => run_components(&args, &grouping, connectivity.into()),
+        Command::Communities { args, top_n, method } => run_communities(&args, top_n, method.into()),
+        Command::Paths { args, grouping, paths } => run_paths(&args, &grouping, &paths),
+        Command::Clustering(args) => run_clustering(&args),
+        Command::Copurchase { args, significance } => run_copurchase(&args, &significance),
+        Command::Mixing { args, top_n, category_depth, unobserved } => {
+            run_mixing(&args, top_n, group_by(category_depth), unobserved)
+        }
+        Command::Report { args, grouping } => run_report(&args, &grouping),
+    }
+}
+
+fn print_parse_report(report: &ParseReport) {
+    eprintln!(
+        "Parsed {} products from {} lines with {} warnings",
+        report.products_read,
+        report.lines_read,
+        report.warnings.len()
+    );
+    for warning in report.warnings.iter().take(10) {
+        eprintln!("  {}", warning);
+    }
+}
+
+fn load(input: &InputArgs) -> Result<AmazonDataCleaner, Box<dyn Error>> {
+    let mut cleaner = AmazonDataCleaner::new(&input.input);
+    if input.no_cache {
+        print_parse_report(&cleaner.load_data()?);
+        cleaner.clean_data();
+    } else {
+        match cleaner.load_cached(&input.snapshot)? {
+            Some(report) => print_parse_report(&report),
+            None => eprintln!("Loaded {} products from {}", cleaner.data.len(), input.snapshot),
+        }
+    }
+    Ok(cleaner)
+}
+
+fn load_sample(args: &AnalysisArgs) -> Result<Sample, Box<dyn Error>> {
+    let cleaner = load(&args.input)?;
+    Ok(draw_sample(cleaner, args, sample_seed(args)))
+}
+
+fn sample_seed(args: &AnalysisArgs) -> u64 {
+    let seed = args.seed.unwrap_or_else(random_seed);
+    eprintln!("Sample seed: {}", seed);
+    seed
+}
+
+fn draw_sample(cleaner: AmazonDataCleaner, args: &AnalysisArgs, seed: u64) -> Sample {
+    if args.sample_size == 0 || args.sample_size >= cleaner.data.len() {
+        return Sample { seed, cleaner };
+    }
+    let sample = cleaner.random_sample_seeded(args.sample_size, seed);
+    Sample {
+        seed,
+        cleaner: AmazonDataCleaner::from_products(sample),
+    }
+}
+
+fn group_by(category_depth: Option<usize>) -> GroupBy {
+    category_depth.map_or(GroupBy::Group, GroupBy::CategoryDepth)
+}
+
+fn open_output(args: &AnalysisArgs) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    Ok(match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    })
+}
+
+// Text goes through `write_text`; every other format is a table of records
+fn write_results<T: Serialize>(
+    args: &AnalysisArgs,
+    sample: &Sample,
+    records: &[T],
+    write_text: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), Box<dyn Error>> {
+    let mut out = open_output(args)?;
+    match args.format.into() {
+        OutputFormat::Text => {
+            writeln!(out, "Sample seed: {}", sample.seed)?;
+            write_text(&mut out)?;
+        }
+        format => write_records(&mut out, format, sample.provenance(), records)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn run_parse(input: &InputArgs, strict: bool) -> Result<(), Box<dyn Error>> {
+    let mode = if strict { ParseMode::Strict } else { ParseMode::Lenient };
+    let mut cleaner = AmazonDataCleaner::new(&input.input);
+    let report = cleaner.load_data_with_mode(mode)?;
+    print_parse_report(&report);
+    cleaner.clean_data();
+    if !input.no_cache {
+        cleaner.save_snapshot(&input.snapshot)?;
+        eprintln!("Wrote snapshot {}", input.snapshot);
+    }
+    Ok(())
+}
+
+fn run_sample(args: &AnalysisArgs, strategy_args: &StrategyArgs) -> Result<(), Box<dyn Error>> {
+    let sample = match strategy_args.strategy {
+        None => load_sample(args)?,
+        Some(strategy) => {
+            let cleaner = load(&args.input)?;
+            let seed = sample_seed(args);
+            let sample_size = if args.sample_size == 0 { cleaner.data.len() } else { args.sample_size };
+            let strategy = strategy_args.sampling_strategy(strategy, sample_size);
+            Sample {
+                seed,
+                cleaner: AmazonDataCleaner::from_products(cleaner.sample_with(&strategy, seed)?),
+            }
+        }
+    };
+    let rows: Vec<ProductNode> = sample.cleaner.data.iter().map(ProductNode::from_product).collect();
+    write_results(args, &sample, &rows, |out| {
+        for row in &rows {
+            writeln!(out, "{}\t{}\t{}", row.id, row.asin.as_deref().unwrap_or(""), row.group)?;
+        }
+        Ok(())
+    })
+}
+
+fn write_summaries(out: &mut dyn Write, summaries: &[CategorySummary]) -> io::Result<()> {
+    writeln!(out, "Top Categories in Random Sample:")?;
+    for summary in summaries {
+        writeln!(out, "Category: {}", summary.category)?;
+        writeln!(out, "  Number of Products: {}", summary.product_count)?;
+        match summary.avg_salesrank {
+            Some(rank) => writeln!(out, "  Average Sales Rank: {:.2}", rank)?,
+            None => writeln!(out, "  Average Sales Rank: No sales ranks available")?,
+        }
+        match summary.avg_rating {
+            Some(rating) => writeln!(out, "  Average Review Rating: {:.2}", rating)?,
+            None => writeln!(out, "  Average Review Rating: No reviews available")?,
+        }
+    }
+    Ok(())
+}
+
+fn run_summarize(args: &AnalysisArgs, grouping: &GroupingArgs) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let summaries = sample.cleaner.summarize_categories(&grouping.summary_options());
+    write_results(args, &sample, &summaries, |out| write_summaries(out, &summaries))
+}
+
+fn format_stat(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v))
+}
+
+fn run_describe(args: &AnalysisArgs, grouping: &GroupingArgs, include_imputed: bool) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let statistics = sample.cleaner.describe_categories(&grouping.summary_options(), include_imputed);
+    let rows: Vec<StatisticsRow> = statistics.iter().flat_map(|s| s.rows()).collect();
+    write_results(args, &sample, &rows, |out| {
+        for category in &statistics {
+            writeln!(out, "Category: {} ({} products)", category.category, category.product_count)?;
+            for row in category.rows() {
+                writeln!(
+                    out,
+                    "  {}: n={} missing={} mean={} sd={} min={} q1={} median={} q3={} max={}",
+                    row.variable,
+                    row.count,
+                    row.missing,
+                    format_stat(row.mean),
+                    format_stat(row.std_dev),
+                    format_stat(row.min),
+                    format_stat(row.q1),
+                    format_stat(row.median),
+                    format_stat(row.q3),
+                    format_stat(row.max)
+                )?;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn run_graph(
+    args: &AnalysisArgs,
+    external_nodes: bool,
+    giant_component: Option<Connectivity>,
+) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let policy = if external_nodes { DanglingPolicy::External } else { DanglingPolicy::Skip };
+    let (mut graph, report) = sample.cleaner.create_global_graph_with(policy);
+    eprintln!(
+        "Global Graph created with {} nodes and {} edges ({} dangling references)",
+        graph.node_count(),
+        graph.edge_count(),
+        report.dangling_references
+    );
+    if let Some(connectivity) = giant_component {
+        graph = extract_giant_component(&graph, connectivity);
+        eprintln!(
+            "Giant component has {} nodes and {} edges",
+            graph.node_count(),
+            graph.edge_count()
+        );
+    }
+
+    let edges: Vec<EdgeRow> = graph
+        .edge_references()
+        .map(|e| EdgeRow {
+            source: e.source().index(),
+            target: e.target().index(),
+            source_asin: graph[e.source()].asin.as_deref(),
+            target_asin: graph[e.target()].asin.as_deref(),
+            direction: e.weight().direction,
+            rank: e.weight().rank,
+            reciprocated: e.weight().reciprocated,
+        })
+        .collect();
+
+    // JSON holds the nodes and edges; the tabular formats and text are edge lists
+    if OutputFormat::from(args.format) == OutputFormat::Json {
+        let mut out = open_output(args)?;
+        let export = GraphExport {
+            nodes: graph.node_weights().collect(),
+            edges,
+        };
+        write_json(&mut out, sample.provenance(), &export)?;
+        out.flush()?;
+        return Ok(());
+    }
+    write_results(args, &sample, &edges, |out| {
+        for edge in &edges {
+            writeln!(
+                out,
+                "{}\t{}",
+                edge.source_asin.unwrap_or(""),
+                edge.target_asin.unwrap_or("")
+            )?;
+        }
+        Ok(())
+    })
+}
+
+// Graphs of the top categories chosen by the summary options
+fn category_graphs(sample: &Sample, options: &SummaryOptions) -> HashMap<String, ProductGraph> {
+    let categories: Vec<String> = sample
+        .cleaner
+        .summarize_categories(options)
+        .into_iter()
+        .map(|s| s.category)
+        .collect();
+    sample.cleaner.create_category_graphs_by(options.group_by, &categories)
+}
+
+fn category_centrality(sample: &Sample, options: &SummaryOptions) -> Vec<CategoryCentrality> {
+    AmazonDataAnalysis::category_centrality_table(&category_graphs(sample, options))
+}
+
+fn write_centrality(out: &mut dyn Write, centrality: &[CategoryCentrality]) -> io::Result<()> {
+    for row in centrality {
+        writeln!(
+            out,
+            "Average Degree Centrality for Category {}: {:.2}",
+            row.category, row.avg_degree_centrality
+        )?;
+    }
+    Ok(())
+}
+
+fn run_centrality(
+    args: &AnalysisArgs,
+    grouping: &GroupingArgs,
+    centrality_args: &CentralityArgs,
+) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    match centrality_args.measure {
+        CentralityMeasure::Average => {
+            let centrality = category_centrality(&sample, &grouping.summary_options());
+            write_results(args, &sample, &centrality, |out| write_centrality(out, &centrality))
+        }
+        CentralityMeasure::Degree => {
+            let graph = sample.cleaner.create_global_graph();
+            let degrees = calculate_degree_centrality(&graph, centrality_args.normalize, centrality_args.top_k);
+            write_nested(args, &sample, &degrees, &degrees.nodes, |out| write_degrees(out, &degrees))
+        }
+        CentralityMeasure::Pagerank => run_pagerank(args, &sample, centrality_args),
+        CentralityMeasure::Eigenvector => run_eigenvector(args, &sample, centrality_args),
+        CentralityMeasure::Hits => run_hits(args, &sample, centrality_args),
+        CentralityMeasure::Betweenness | CentralityMeasure::Closeness | CentralityMeasure::Harmonic => {
+            let graph = sample.cleaner.create_global_graph();
+            let mode = centrality_args.path_centrality_mode(sample.seed);
+            let paths = calculate_path_centrality(&graph, mode, centrality_args.top_k);
+            write_nested(args, &sample, &paths, &paths.nodes, |out| {
+                write_path_centrality(out, &paths, centrality_args.measure)
+            })
+        }
+    }
+}
+
+fn write_path_centrality(out: &mut dyn Write, paths: &PathCentrality, measure: CentralityMeasure) -> io::Result<()> {
+    let (label, stats, top, score): (_, _, _, fn(&NodePathCentrality) -> f64) = match measure {
+        CentralityMeasure::Betweenness => {
+            ("betweenness", &paths.betweenness_stats, &paths.top_betweenness, |n| n.betweenness)
+        }
+        CentralityMeasure::Closeness => ("closeness", &paths.closeness_stats, &paths.top_closeness, |n| n.closeness),
+        _ => ("harmonic centrality", &paths.harmonic_stats, &paths.top_harmonic, |n| n.harmonic),
+    };
+    match paths.error_bound {
+        Some(bound) => writeln!(
+            out,
+            "Approximate {} over {} products from {} pivots (error bound {:.4}):",
+            label, paths.node_count, paths.pivots, bound
+        )?,
+        None => writeln!(out, "Exact {} over {} products:", label, paths.node_count)?,
+    }
+    write_stats_line(out, "Score", stats)?;
+    writeln!(out, "Top products by {}:", label)?;
+    for node in top {
+        writeln!(out, "  Product ID: {} ({}) - {:.6}", node.product_id, node.group, score(node))?;
+    }
+    Ok(())
+}
+
+fn run_pagerank(args: &AnalysisArgs, sample: &Sample, centrality_args: &CentralityArgs) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct PageRankReport<'a> {
+        options: PageRankOptions,
+        seed_count: usize,
+        convergence: Convergence,
+        top: &'a [RankedProduct],
+    }
+
+    let graph = sample.cleaner.create_global_graph();
+    let mut seeds = seed_nodes_by_asin(&graph, &centrality_args.personalize_asins);
+    if let Some(group) = &centrality_args.personalize_group {
+        seeds.extend(seed_nodes_by_group(&graph, group));
+    }
+    let personalized = !centrality_args.personalize_asins.is_empty() || centrality_args.personalize_group.is_some();
+    if personalized && seeds.is_empty() {
+        return Err("none of the personalization ASINs or groups are in the sampled graph".into());
+    }
+
+    let options = centrality_args.pagerank_options();
+    let result = calculate_personalized_pagerank(&graph, &options, &seeds);
+    let ranked = sample.cleaner.rank_products(&graph, &result.top_k(graph.node_count()));
+    let top = &ranked[..centrality_args.top_k.min(ranked.len())];
+    let report = PageRankReport {
+        options,
+        seed_count: seeds.len(),
+        convergence: result.convergence,
+        top,
+    };
+    write_nested(args, sample, &report, &ranked, |out| {
+        writeln!(
+            out,
+            "PageRank over {} products ({}):",
+            graph.node_count(),
+            describe_convergence(&result.convergence)
+        )?;
+        write_ranked(out, top)
+    })
+}
+
+fn describe_convergence(convergence: &Convergence) -> String {
+    format!(
+        "{} iterations, {}",
+        convergence.iterations,
+        if convergence.converged { "converged" } else { "not converged" }
+    )
+}
+
+fn run_eigenvector(
+    args: &AnalysisArgs,
+    sample: &Sample,
+    centrality_args: &CentralityArgs,
+) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct EigenvectorReport<'a> {
+        convergence: Convergence,
+        top: &'a [RankedProduct],
+    }
+
+    let graph = sample.cleaner.create_global_graph();
+    let result = calculate_eigenvector_centrality(&graph, &centrality_args.power_iteration_options());
+    let ranked = sample.cleaner.rank_products(&graph, &top_k_scores(&result.scores, graph.node_count()));
+    let top = &ranked[..centrality_args.top_k.min(ranked.len())];
+    let report = EigenvectorReport {
+        convergence: result.convergence,
+        top,
+    };
+    write_nested(args, sample, &report, &ranked, |out| {
+        writeln!(
+            out,
+            "Eigenvector centrality over {} products ({}):",
+            graph.node_count(),
+            describe_convergence(&result.convergence)
+        )?;
+        write_ranked(out, top)
+    })
+}
+
+fn run_hits(args: &AnalysisArgs, sample: &Sample, centrality_args: &CentralityArgs) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct HitsReport {
+        convergence: Convergence,
+        top_hubs: Vec<RankedProduct>,
+        top_authorities: Vec<RankedProduct>,
+    }
+
+    let graph = sample.cleaner.create_global_graph();
+    let result = calculate_hits(&graph, &centrality_args.power_iteration_options());
+    let products = sample.cleaner.hits_products(&graph, &result.hubs, &result.authorities);
+    let report = HitsReport {
+        convergence: result.convergence,
+        top_hubs: sample.cleaner.rank_products(&graph, &top_k_scores(&result.hubs, centrality_args.top_k)),
+        top_authorities: sample
+            .cleaner
+            .rank_products(&graph, &top_k_scores(&result.authorities, centrality_args.top_k)),
+    };
+    write_nested(args, sample, &report, &products, |out| {
+        writeln!(out, "HITS over {} products ({}):", graph.node_count(), describe_convergence(&report.convergence))?;
+        writeln!(out, "Top hubs:")?;
+        write_ranked(out, &report.top_hubs)?;
+        writeln!(out, "Top authorities:")?;
+        write_ranked(out, &report.top_authorities)
+    })
+}
+
+fn write_ranked(out: &mut dyn Write, ranked: &[RankedProduct]) -> io::Result<()> {
+    for product in ranked {
+        writeln!(
+            out,
+            "  {}. {} [{}] ({}) - {:.6}",
+            product.rank,
+            product.title.as_deref().unwrap_or("(no title)"),
+            product.asin.as_deref().unwrap_or("-"),
+            product.group,
+            product.score
+        )?;
+    }
+    Ok(())
+}
+
+// Like `write_results`, for results with a nested JSON form; JSON Lines and CSV get `rows`
+fn write_nested<T: Serialize, R: Serialize>(
+    args: &AnalysisArgs,
+    sample: &Sample,
+    result: &T,
+    rows: &[R],
+    write_text: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), Box<dyn Error>> {
+    if OutputFormat::from(args.format) != OutputFormat::Json {
+        return write_results(args, sample, rows, write_text);
+    }
+    let mut out = open_output(args)?;
+    write_json(&mut out, sample.provenance(), result)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn write_stats_line(out: &mut dyn Write, label: &str, stats: &DescriptiveStats) -> io::Result<()> {
+    writeln!(
+        out,
+        "  {}: mean={} sd={} median={} max={}",
+        label,
+        format_stat(stats.mean),
+        format_stat(stats.std_dev),
+        format_stat(stats.median),
+        format_stat(stats.max)
+    )
+}
+
+fn write_degrees(out: &mut dyn Write, degrees: &DegreeCentrality) -> io::Result<()> {
+    writeln!(
+        out,
+        "Degree centrality over {} products{}:",
+        degrees.node_count,
+        if degrees.normalized { " (normalized)" } else { "" }
+    )?;
+    write_stats_line(out, "In-degree", &degrees.in_degree_stats)?;
+    write_stats_line(out, "Out-degree", &degrees.out_degree_stats)?;
+    write_stats_line(out, "Total degree", &degrees.total_degree_stats)?;
+    for (label, top) in [("in", &degrees.top_in), ("out", &degrees.top_out), ("total", &degrees.top_total)] {
+        writeln!(out, "Top products by {}-degree:", label)?;
+        for node in top {
+            writeln!(
+                out,
+                "  Product ID: {} ({}) - in {:.4}, out {:.4}, total {:.4}",
+                node.product_id, node.group, node.in_centrality, node.out_centrality, node.total_centrality
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// JSON holds both tables; the other formats get the one chosen by `neighbor_degree`
+fn run_assortativity(args: &AnalysisArgs, neighbor_degree: bool) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let graph = sample.cleaner.create_global_graph();
+    let assortativity = AmazonDataAnalysis::assortativity(&graph);
+    if neighbor_degree {
+        return write_nested(args, &sample, &assortativity, &assortativity.neighbor_degree, |out| {
+            for row in &assortativity.neighbor_degree {
+                writeln!(
+                    out,
+                    "{} degree {}: {} products, average neighbour degree {:.2}",
+                    row.variant, row.degree, row.node_count, row.avg_neighbor_degree
+                )?;
+            }
+            Ok(())
+        });
+    }
+    write_nested(args, &sample, &assortativity, &assortativity.coefficients, |out| {
+        for row in &assortativity.coefficients {
+            writeln!(
+                out,
+                "Assortativity ({}): {} over {} edges",
+                row.measure,
+                format_stat(row.coefficient),
+                row.pairs
+            )?;
+        }
+        Ok(())
+    })
+}
+
+// The global graph's components are the first row, under the category "(global)"
+fn run_components(
+    args: &AnalysisArgs,
+    grouping: &GroupingArgs,
+    connectivity: Connectivity,
+) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct ComponentReport<'a> {
+        global: &'a ComponentAnalysis,
+        categories: &'a [CategoryComponents],
+    }
+
+    let sample = load_sample(args)?;
+    let global = analyze_components(&sample.cleaner.create_global_graph(), connectivity);
+    let categories =
+        AmazonDataAnalysis::component_table(&category_graphs(&sample, &grouping.summary_options()), connectivity);
+    let mut rows = vec![CategoryComponents::from_analysis("(global)", &global)];
+    rows.extend(categories.iter().cloned());
+
+    let report = ComponentReport {
+        global: &global,
+        categories: &categories,
+    };
+    write_nested(args, &sample, &report, &rows, |out| {
+        writeln!(
+            out,
+            "{} {:?} components over {} products; the giant component holds {} ({:.2}%)",
+            global.component_count,
+            connectivity,
+            global.node_count,
+            global.giant_size,
+            global.giant_share * 100.0
+        )?;
+        writeln!(out, "Component sizes:")?;
+        for size in &global.size_distribution {
+            writeln!(out, "  {} nodes: {} components", size.size, size.count)?;
+        }
+        for row in &categories {
+            writeln!(
+                out,
+                "Category {}: {} components, giant component {} of {} products ({:.2}%)",
+                row.category,
+                row.component_count,
+                row.giant_size,
+                row.node_count,
+                row.giant_share * 100.0
+            )?;
+        }
+        Ok(())
+    })
+}
+
+// Communities are seeded with the sample seed, so a run can be repeated exactly
+fn run_communities(args: &AnalysisArgs, top_n: usize, method: CommunityMethod) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let communities = detect_communities(&sample.cleaner.create_global_graph(), method, sample.seed);
+    write_nested(args, &sample, &communities, &communities.assignments, |out| {
+        write_communities(out, &communities, top_n)
+    })
+}
+
+fn write_communities(out: &mut dyn Write, communities: &CommunityDetection, top_n: usize) -> io::Result<()> {
+    writeln!(
+        out,
+        "{} communities over {} products after {} passes; modularity {:.4}",
+        communities.community_count, communities.node_count, communities.passes, communities.modularity
+    )?;
+    writeln!(
+        out,
+        "Agreement with product groups: NMI {:.4}, ARI {:.4}",
+        communities.nmi, communities.ari
+    )?;
+    let shown = if top_n == 0 { communities.sizes.len() } else { top_n };
+    for size in communities.sizes.iter().take(shown) {
+        let groups: Vec<String> = communities
+            .contingency
+            .iter()
+            .filter(|cell| cell.community == size.community)
+            .map(|cell| format!("{} {}", cell.category, cell.count))
+            .collect();
+        writeln!(out, "Community {}: {} products ({})", size.community, size.size, groups.join(", "))?;
+    }
+    Ok(())
+}
+
+fn run_paths(args: &AnalysisArgs, grouping: &GroupingArgs, path_args: &PathArgs) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let directed = !path_args.undirected;
+    match (&path_args.from, &path_args.to) {
+        (Some(from), Some(to)) => run_shortest_path(args, &sample, from, to, directed),
+        _ => {
+            let (global_mode, category_mode) = path_args.distance_modes(sample.seed);
+            run_distances(args, &sample, grouping, directed, global_mode, category_mode)
+        }
+    }
+}
+
+fn run_shortest_path(
+    args: &AnalysisArgs,
+    sample: &Sample,
+    from: &str,
+    to: &str,
+    directed: bool,
+) -> Result<(), Box<dyn Error>> {
+    let graph = sample.cleaner.create_global_graph();
+    for asin in [from, to] {
+        if seed_nodes_by_asin(&graph, &[asin.to_string()]).is_empty() {
+            return Err(format!("ASIN {} is not in the sampled graph", asin).into());
+        }
+    }
+    let Some(path) = AmazonDataAnalysis::shortest_path_between(&sample.cleaner, &graph, from, to, directed) else {
+        return Err(format!("no co-purchase path from {} to {}", from, to).into());
+    };
+    write_results(args, sample, &path, |out| {
+        writeln!(out, "{} -> {} in {} hops:", from, to, path.len() - 1)?;
+        for step in &path {
+            writeln!(
+                out,
+                "  {}. {} [{}] ({})",
+                step.hop,
+                step.title.as_deref().unwrap_or("(no title)"),
+                step.asin.as_deref().unwrap_or("-"),
+                step.group
+            )?;
+        }
+        Ok(())
+    })
+}
+
+// The global graph is the first row, under the category "(global)"
+fn run_distances(
+    args: &AnalysisArgs,
+    sample: &Sample,
+    grouping: &GroupingArgs,
+    directed: bool,
+    global_mode: DistanceMode,
+    category_mode: DistanceMode,
+) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct DistanceReport<'a> {
+        global: &'a DistanceSummary,
+        categories: &'a [CategoryDistances],
+    }
+
+    let global = calculate_distances(&sample.cleaner.create_global_graph(), directed, global_mode);
+    let graphs = category_graphs(sample, &grouping.summary_options());
+    let categories = AmazonDataAnalysis::distance_table(&graphs, directed, category_mode);
+    let mut rows = vec![CategoryDistances::from_summary("(global)", &global)];
+    rows.extend(categories.iter().cloned());
+
+    let report = DistanceReport {
+        global: &global,
+        categories: &categories,
+    };
+    write_nested(args, sample, &report, &rows, |out| {
+        for row in &rows {
+            writeln!(
+                out,
+                "Category {}: diameter {}{}, average path length {} over {} reachable pairs",
+                row.category,
+                if row.exact { "" } else { ">= " },
+                row.diameter,
+                format_stat(row.avg_path_length),
+                row.reachable_pairs
+            )?;
+        }
+        Ok(())
+    })
+}
+
+// The whole graph is the first row, under the category "(global)"
+fn run_clustering(args: &AnalysisArgs) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let clustering = calculate_clustering(&sample.cleaner.create_global_graph());
+    let mut rows = vec![clustering.overall("(global)")];
+    rows.extend(clustering.categories.iter().cloned());
+
+    write_nested(args, &sample, &clustering, &rows, |out| {
+        writeln!(
+            out,
+            "{} triangles over {} products; average clustering {:.4}, transitivity {:.4}",
+            clustering.triangle_count, clustering.node_count, clustering.avg_clustering, clustering.transitivity
+        )?;
+        for row in &clustering.categories {
+            writeln!(
+                out,
+                "Category: {} - Triangle Corners: {}, Average Clustering: {:.4}, Transitivity: {:.4}",
+                row.category, row.triangle_corners, row.avg_clustering, row.transitivity
+            )?;
+        }
+        Ok(())
+    })
+}
+
+fn co_purchase_ratios(sample: &Sample) -> Vec<CoPurchaseRatio> {
+    AmazonDataAnalysis::co_purchase_ratio_table(&sample.cleaner.create_global_graph())
+}
+
+fn write_ratios(out: &mut dyn Write, ratios: &[CoPurchaseRatio]) -> io::Result<()> {
+    writeln!(out, "Co-Purchase Ratios:")?;
+    for row in ratios {
+        writeln!(
+            out,
+            "Category: {} - In-Category Ratio: {:.2}, Cross-Category Ratio: {:.2}",
+            row.category, row.in_category_ratio, row.cross_category_ratio
+        )?;
+    }
+    Ok(())
+}
+
+// Subsamples are drawn from the full data with seeds derived from the sample
+// seed, and the permutation test shuffles labels on the sampled graph
+fn run_copurchase(args: &AnalysisArgs, significance: &SignificanceArgs) -> Result<(), Box<dyn Error>> {
+    if significance.subsamples == 0 && significance.permutations == 0 {
+        let sample = load_sample(args)?;
+        let ratios = co_purchase_ratios(&sample);
+        return write_results(args, &sample, &ratios, |out| write_ratios(out, &ratios));
+    }
+
+    let cleaner = load(&args.input)?;
+    let seed = sample_seed(args);
+    let intervals = if significance.subsamples > 0 {
+        AmazonDataAnalysis::subsample_co_purchase_ratios(
+            &cleaner,
+            args.sample_size,
+            significance.subsamples,
+            significance.confidence,
+            seed,
+        )?
+    } else {
+        HashMap::new()
+    };
+    let sample = draw_sample(cleaner, args, seed);
+    let graph = sample.cleaner.create_global_graph();
+    let tests = if significance.permutations > 0 {
+        AmazonDataAnalysis::permutation_test_co_purchase(&graph, significance.permutations, seed)
+    } else {
+        HashMap::new()
+    };
+    let ratios = AmazonDataAnalysis::co_purchase_ratio_table(&graph);
+    let table = AmazonDataAnalysis::co_purchase_significance_table(&ratios, &intervals, &tests);
+
+    write_results(args, &sample, &table, |out| {
+        writeln!(out, "Co-Purchase Ratios:")?;
+        for row in &table {
+            write!(
+                out,
+                "Category: {} - In-Category Ratio: {:.2}, Cross-Category Ratio: {:.2}",
+                row.category, row.in_category_ratio, row.cross_category_ratio
+            )?;
+            let interval = (row.interval_lower, row.interval_upper, row.interval_sample_size);
+            if let (Some(lower), Some(upper), Some(n)) = interval {
+                write!(
+                    out,
+                    ", {:.0}% subsampling interval at n={} [{:.2}, {:.2}]",
+                    significance.confidence * 100.0,
+                    n,
+                    lower,
+                    upper
+                )?;
+            }
+            if let (Some(null), Some(p_value)) = (row.null_in_category_ratio, row.p_value) {
+                write!(out, ", shuffled {:.2} (p = {:.4})", null, p_value)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    })
+}
+
+fn run_mixing(args: &AnalysisArgs, top_n: usize, group_by: GroupBy, unobserved: bool) -> Result<(), Box<dyn Error>> {
+    let sample = load_sample(args)?;
+    let graph = sample.cleaner.create_global_graph();
+    let labels = sample.cleaner.node_labels(&graph, group_by);
+    let mixing = calculate_mixing_matrix(&graph, &labels, unobserved);
+    write_nested(args, &sample, &mixing, &mixing.cells, |out| write_mixing(out, &mixing, top_n))
+}
+
+// The `top_n` most common targets of each source label. Cells are sorted by source,
+// so each label's row is one contiguous run.
+fn write_mixing(out: &mut dyn Write, mixing: &MixingMatrix, top_n: usize) -> io::Result<()> {
+    writeln!(
+        out,
+        "Mixing over {} labelled edges ({} unlabelled); assortativity {}",
+        mixing.edge_count,
+        mixing.unlabelled_edges,
+        format_stat(mixing.assortativity)
+    )?;
+    for cells in mixing.cells.chunk_by(|a, b| a.source == b.source) {
+        let mut row: Vec<_> = cells.iter().filter(|c| c.count > 0.0).collect();
+        row.sort_by(|a, b| b.count.total_cmp(&a.count).then(a.target.cmp(&b.target)));
+        if top_n > 0 {
+            row.truncate(top_n);
+        }
+        for cell in row {
+            writeln!(
+                out,
+                "{} -> {}: {:.1} edges (p = {:.4}, expected {:.1})",
+                cell.source, cell.target, cell.count, cell.probability, cell.expected
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The report has several tables: JSON nests them, JSON Lines tags each row with its
+// section, and CSV is refused because one file can only hold one table
+fn run_report(args: &AnalysisArgs, grouping: &GroupingArgs) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct Report {
+        top_categories: Vec<CategorySummary>,
+        degree_centrality: Vec<CategoryCentrality>,
+        co_purchase_ratios: Vec<CoPurchaseRatio>,
+    }
+
+    if OutputFormat::from(args.format) == OutputFormat::Csv {
+        return Err("the report has several tables; use summarize, centrality or copurchase for CSV".into());
+    }
+
+    let sample = load_sample(args)?;
+    let report = Report {
+        top_categories: sample.cleaner.summarize_categories(&grouping.summary_options()),
+        degree_centrality: category_centrality(&sample, &grouping.summary_options()),
+        co_purchase_ratios: co_purchase_ratios(&sample),
+    };
+
+    let mut out = open_output(args)?;
+    match args.format.into() {
+        OutputFormat::Text => {
+            writeln!(out, "Sample seed: {}", sample.seed)?;
+            write_summaries(&mut out, &report.top_categories)?;
+            write_centrality(&mut out, &report.degree_centrality)?;
+            writeln!(out)?;
+            write_ratios(&mut out, &report.co_purchase_ratios)?;
+        }
+        OutputFormat::Json => write_json(&mut out, sample.provenance(), &report)?,
+        format => {
+            let mut rows = section_rows("top_categories", &report.top_categories)?;
+            rows.extend(section_rows("degree_centrality", &report.degree_centrality)?);
+            rows.extend(section_rows("co_purchase_ratios", &report.co_purchase_ratios)?);
+            write_records(&mut out, format, sample.provenance(), &rows)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn section_rows<T: Serialize>(section: &'static str, rows: &[T]) -> Result<Vec<serde_json::Value>, serde_json::Error> {
+    #[derive(Serialize)]
+    struct SectionRow<'a, T: Serialize> {
+        section: &'static str,
+        #[serde(flatten)]
+        row: &'a T,
+    }
+
+    rows.iter()
+        .map(|row| serde_json::to_value(SectionRow { section, row }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{temp_dir, SAMPLE_META};
+
+    fn parse(args: &[&str]) -> Result<Command, clap::Error> {
+        Cli::try_parse_from([&["final_project"], args].concat()).map(|cli| cli.command)
+    }
+
+    #[test]
+    fn test_analysis_flags_and_defaults() {
+        match parse(&["summarize", "--seed", "5", "--top-n", "10", "--format", "json"]).unwrap() {
+            Command::Summarize { args, grouping, detailed: false, .. } => {
+                assert_eq!(args.seed, Some(5));
+                assert_eq!(grouping.top_n, 10);
+                assert_eq!(args.sample_size, 100000);
+                assert_eq!(args.format, OutputFormatArg::Json);
+                assert_eq!(args.input.input, "amazon-meta.txt");
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_grouping_flags_only_on_grouped_commands() {
+        assert!(parse(&["sample", "--top-n", "10"]).is_err());
+        assert!(parse(&["graph", "--category-depth", "2"]).is_err());
+        assert!(parse(&["mixing", "--rank-by", "rating"]).is_err());
+        assert!(parse(&["mixing", "--top-n", "5", "--category-depth", "2"]).is_ok());
+    }
+
+    #[test]
+    fn test_sample_strategy_flags() {
+        match parse(&["sample", "--strategy", "forest-fire", "--forward-probability", "1"]).unwrap() {
+            Command::Sample { strategy, .. } => assert_eq!(
+                strategy.sampling_strategy(StrategyArg::ForestFire, 40),
+                SamplingStrategy::ForestFire { sample_size: 40, forward_probability: 1.0 }
+            ),
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_centrality_samples_pivots_unless_exact() {
+        let centrality_mode = |flags: &[&str]| {
+            match parse(&[&["centrality", "--measure", "betweenness"], flags].concat()) {
+                Ok(Command::Centrality { centrality, .. }) => Some(centrality.path_centrality_mode(7)),
+                _ => None,
+            }
+        };
+        assert_eq!(
+            centrality_mode(&[]),
+            Some(PathCentralityMode::Approximate { epsilon: 0.1, delta: 0.1, seed: 7 })
+        );
+        assert_eq!(centrality_mode(&["--exact"]), Some(PathCentralityMode::Exact));
+        assert_eq!(centrality_mode(&["--exact", "--epsilon", "0.2"]), None);
+        assert_eq!(centrality_mode(&["--exact", "--delta", "0.2"]), None);
+    }
+
+    #[test]
+    fn test_paths_estimate_the_global_graph_only() {
+        let distance_modes = |flags: &[&str]| match parse(&[&["paths", "--sweeps", "3"], flags].concat()) {
+            Ok(Command::Paths { paths, .. }) => Some(paths.distance_modes(9)),
+            _ => None,
+        };
+        let estimate = DistanceMode::Approximate { sweeps: 3, sources: 100, seed: 9 };
+        assert_eq!(distance_modes(&[]), Some((estimate, DistanceMode::Exact)));
+        assert_eq!(distance_modes(&["--exact"]), Some((DistanceMode::Exact, DistanceMode::Exact)));
+        assert_eq!(distance_modes(&["--approximate"]), Some((estimate, estimate)));
+        assert_eq!(distance_modes(&["--exact", "--approximate"]), None);
+    }
+
+    #[test]
+    fn test_confidence_must_be_strictly_between_zero_and_one() {
+        for confidence in ["1.5", "0", "1", "NaN"] {
+            assert!(parse(&["copurchase", "--confidence", confidence]).is_err());
+        }
+        assert!(parse(&["copurchase", "--confidence", "0.9"]).is_ok());
+    }
+
+    // Runs `copurchase` on `SAMPLE_META` and returns what it wrote
+    fn run_copurchase(format: &str) -> String {
+        let dir = temp_dir(&format!("cli-{}", format));
+        let source = dir.join("meta.txt");
+        let output = dir.join("ratios");
+        std::fs::write(&source, SAMPLE_META).unwrap();
+        let cli = Cli::try_parse_from([
+            "final_project",
+            "copurchase",
+            "--input",
+            source.to_str().unwrap(),
+            "--no-cache",
+            "--seed",
+            "1",
+            "--format",
+            format,
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .unwrap();
+        run(cli).unwrap();
+        let written = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        written
+    }
+
+    #[test]
+    fn test_json_output_has_provenance() {
+        let written: serde_json::Value = serde_json::from_str(&run_copurchase("json")).unwrap();
+        assert_eq!(written["seed"], 1);
+        assert_eq!(written["sample_size"], 3);
+        assert_eq!(
+            written["results"][0],
+            serde_json::json!({"category": "Book", "in_category_ratio": 1.0, "cross_category_ratio": 0.0})
+        );
+    }
+
+    #[test]
+    fn test_json_lines_output_has_provenance_per_record() {
+        let written = run_copurchase("jsonl");
+        let line: serde_json::Value = serde_json::from_str(written.lines().next().unwrap()).unwrap();
+        assert_eq!(line["seed"], 1);
+        assert_eq!(line["category"], "Book");
+    }
+
+    #[test]
+    fn test_csv_output_has_provenance_columns() {
+        assert_eq!(
+            run_copurchase("csv"),
+            "seed,sample_size,category,in_category_ratio,cross_category_ratio\n1,3,Book,1.0,0.0\n"
+        );
+    }
+}
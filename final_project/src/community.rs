@@ -0,0 +1,431 @@
+use crate::data_analysis::undirected_adjacency;
+use crate::product_graph::GroupedNode;
+use crate::sampling::{seeded_rng, SampleRng};
+use petgraph::graph::Graph;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// Label propagation stops after this many passes even if labels still change
+const MAX_LABEL_PROPAGATION_PASSES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommunityMethod {
+    // Greedy modularity optimisation, merging communities level by level
+    #[default]
+    Louvain,
+    // Each product repeatedly takes the most common label among its neighbours
+    LabelPropagation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommunityAssignment {
+    pub node: usize,
+    pub product_id: u32,
+    pub group: String,
+    pub community: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommunitySize {
+    pub community: usize,
+    pub size: usize,
+}
+
+// How many products of one category ended up in one community
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContingencyCell {
+    pub community: usize,
+    pub category: String,
+    pub count: usize,
+}
+
+// Communities are found on the undirected view of the graph and numbered largest first
+// (ties by lowest node index). `passes` is the number of Louvain levels or label
+// propagation sweeps. `nmi` and `ari` compare the communities with the product groups.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommunityDetection {
+    pub method: CommunityMethod,
+    pub node_count: usize,
+    pub community_count: usize,
+    pub passes: usize,
+    pub modularity: f64,
+    pub nmi: f64,
+    pub ari: f64,
+    pub sizes: Vec<CommunitySize>,
+    pub contingency: Vec<ContingencyCell>,
+    pub assignments: Vec<CommunityAssignment>,
+}
+
+pub fn detect_communities<N: GroupedNode, E>(
+    graph: &Graph<N, E>,
+    method: CommunityMethod,
+    seed: u64,
+) -> CommunityDetection {
+    let adjacency = undirected_adjacency(graph);
+    let mut rng = seeded_rng(seed);
+    let (membership, passes) = match method {
+        CommunityMethod::Louvain => louvain(&adjacency, &mut rng),
+        CommunityMethod::LabelPropagation => label_propagation(&adjacency, &mut rng),
+    };
+    let (membership, sizes) = renumber_by_size(&membership);
+
+    let mut group_ids: BTreeMap<&str, usize> = BTreeMap::new();
+    for node in graph.node_weights() {
+        let next = group_ids.len();
+        group_ids.entry(node.group()).or_insert(next);
+    }
+    let groups: Vec<usize> = graph.node_weights().map(|node| group_ids[node.group()]).collect();
+
+    let mut cells: BTreeMap<(usize, &str), usize> = BTreeMap::new();
+    for (node, &community) in graph.node_weights().zip(&membership) {
+        *cells.entry((community, node.group())).or_insert(0) += 1;
+    }
+
+    CommunityDetection {
+        method,
+        node_count: membership.len(),
+        community_count: sizes.len(),
+        passes,
+        modularity: modularity(&adjacency, &membership),
+        nmi: normalized_mutual_information(&membership, &groups),
+        ari: adjusted_rand_index(&membership, &groups),
+        sizes: sizes
+            .into_iter()
+            .enumerate()
+            .map(|(community, size)| CommunitySize { community, size })
+            .collect(),
+        contingency: cells
+            .into_iter()
+            .map(|((community, category), count)| ContingencyCell {
+                community,
+                category: category.to_string(),
+                count,
+            })
+            .collect(),
+        assignments: graph
+            .node_indices()
+            .map(|node| CommunityAssignment {
+                node: node.index(),
+                product_id: graph[node].product_id(),
+                group: graph[node].group().to_string(),
+                community: membership[node.index()],
+            })
+            .collect(),
+    }
+}
+
+// Relabel communities 0, 1, ... from largest to smallest, ties by lowest member node;
+// also returns the community sizes in that order
+fn renumber_by_size(membership: &[usize]) -> (Vec<usize>, Vec<usize>) {
+    let mut members: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    for (node, &community) in membership.iter().enumerate() {
+        members.entry(community).or_insert((0, node)).0 += 1;
+    }
+    let mut order: Vec<(usize, (usize, usize))> = members.into_iter().collect();
+    order.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.1 .1.cmp(&b.1 .1)));
+
+    let new_ids: HashMap<usize, usize> = order.iter().enumerate().map(|(id, &(old, _))| (old, id)).collect();
+    let sizes = order.iter().map(|&(_, (size, _))| size).collect();
+    (membership.iter().map(|community| new_ids[community]).collect(), sizes)
+}
+
+// Newman's modularity of a partition of an undirected, unweighted graph
+pub fn modularity(adjacency: &[Vec<usize>], membership: &[usize]) -> f64 {
+    let total_degree: usize = adjacency.iter().map(Vec::len).sum();
+    if total_degree == 0 {
+        return 0.0;
+    }
+    let total_degree = total_degree as f64;
+
+    let mut internal = 0.0;
+    let mut community_degree: HashMap<usize, f64> = HashMap::new();
+    for (node, neighbors) in adjacency.iter().enumerate() {
+        internal += neighbors.iter().filter(|&&n| membership[n] == membership[node]).count() as f64;
+        *community_degree.entry(membership[node]).or_insert(0.0) += neighbors.len() as f64;
+    }
+    internal / total_degree
+        - community_degree
+            .values()
+            .map(|degree| (degree / total_degree).powi(2))
+            .sum::<f64>()
+}
+
+// Weighted undirected graph for one Louvain level. `neighbors[i]` leaves out i itself;
+// `self_loops[i]` is the weight of the edges folded inside node i.
+struct LevelGraph {
+    neighbors: Vec<Vec<(usize, f64)>>,
+    self_loops: Vec<f64>,
+}
+
+impl LevelGraph {
+    fn from_adjacency(adjacency: &[Vec<usize>]) -> Self {
+        LevelGraph {
+            neighbors: adjacency
+                .iter()
+                .map(|neighbors| neighbors.iter().map(|&n| (n, 1.0)).collect())
+                .collect(),
+            self_loops: vec![0.0; adjacency.len()],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    fn degree(&self, node: usize) -> f64 {
+        2.0 * self.self_loops[node] + self.neighbors[node].iter().map(|&(_, w)| w).sum::<f64>()
+    }
+
+    // One node per community, with the edges between communities summed
+    fn aggregate(&self, community: &[usize], community_count: usize) -> LevelGraph {
+        let mut self_loops = vec![0.0; community_count];
+        let mut links: Vec<BTreeMap<usize, f64>> = vec![BTreeMap::new(); community_count];
+        for node in 0..self.len() {
+            let c = community[node];
+            self_loops[c] += self.self_loops[node];
+            for &(neighbor, weight) in &self.neighbors[node] {
+                let d = community[neighbor];
+                if c == d {
+                    // Seen once from each end
+                    self_loops[c] += weight / 2.0;
+                } else {
+                    *links[c].entry(d).or_insert(0.0) += weight;
+                }
+            }
+        }
+        LevelGraph {
+            neighbors: links.into_iter().map(|l| l.into_iter().collect()).collect(),
+            self_loops,
+        }
+    }
+}
+
+// Louvain's local moving phase: move nodes, in random order, to the neighbouring
+// community with the largest modularity gain until no move helps. Returns communities
+// numbered from 0 and whether any node moved.
+fn move_nodes(graph: &LevelGraph, rng: &mut SampleRng) -> (Vec<usize>, usize, bool) {
+    let node_count = graph.len();
+    let degree: Vec<f64> = (0..node_count).map(|node| graph.degree(node)).collect();
+    let total_degree: f64 = degree.iter().sum();
+    let mut community: Vec<usize> = (0..node_count).collect();
+    if total_degree == 0.0 {
+        return (community, node_count, false);
+    }
+
+    let mut community_degree = degree.clone();
+    let mut link_weight = vec![0.0; node_count];
+    let mut touched = Vec::new();
+    let mut order: Vec<usize> = (0..node_count).collect();
+    order.shuffle(rng);
+    let mut moved = false;
+
+    loop {
+        let mut moved_this_pass = false;
+        for &node in &order {
+            let current = community[node];
+            for &(neighbor, weight) in &graph.neighbors[node] {
+                let c = community[neighbor];
+                if link_weight[c] == 0.0 {
+                    touched.push(c);
+                }
+                link_weight[c] += weight;
+            }
+
+            community_degree[current] -= degree[node];
+            let gain = |c: usize| link_weight[c] - community_degree[c] * degree[node] / total_degree;
+            let mut best = current;
+            let mut best_gain = gain(current);
+            for &c in &touched {
+                // Only a strictly better community is worth leaving for
+                if gain(c) > best_gain + 1e-12 {
+                    best = c;
+                    best_gain = gain(c);
+                }
+            }
+            community_degree[best] += degree[node];
+            if best != current {
+                community[node] = best;
+                moved = true;
+                moved_this_pass = true;
+            }
+
+            for c in touched.drain(..) {
+                link_weight[c] = 0.0;
+            }
+        }
+        if !moved_this_pass {
+            break;
+        }
+    }
+
+    let mut ids: HashMap<usize, usize> = HashMap::new();
+    for c in community.iter_mut() {
+        let next = ids.len();
+        *c = *ids.entry(*c).or_insert(next);
+    }
+    (community, ids.len(), moved)
+}
+
+fn louvain(adjacency: &[Vec<usize>], rng: &mut SampleRng) -> (Vec<usize>, usize) {
+    let mut membership: Vec<usize> = (0..adjacency.len()).collect();
+    let mut graph = LevelGraph::from_adjacency(adjacency);
+    let mut levels = 0;
+
+    loop {
+        let (community, community_count, moved) = move_nodes(&graph, rng);
+        if !moved {
+            break;
+        }
+        for c in membership.iter_mut() {
+            *c = community[*c];
+        }
+        levels += 1;
+        if community_count == graph.len() {
+            break;
+        }
+        graph = graph.aggregate(&community, community_count);
+    }
+    (membership, levels)
+}
+
+// Asynchronous label propagation in random order. A node keeps its label while that
+// label is among the most common around it, so the sweeps stop once labels are stable.
+fn label_propagation(adjacency: &[Vec<usize>], rng: &mut SampleRng) -> (Vec<usize>, usize) {
+    let mut labels: Vec<usize> = (0..adjacency.len()).collect();
+    let mut order: Vec<usize> = (0..adjacency.len()).collect();
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    let mut passes = 0;
+
+    while passes < MAX_LABEL_PROPAGATION_PASSES {
+        passes += 1;
+        order.shuffle(rng);
+        let mut changed = false;
+        for &node in &order {
+            if adjacency[node].is_empty() {
+                continue;
+            }
+            counts.clear();
+            for &neighbor in &adjacency[node] {
+                *counts.entry(labels[neighbor]).or_insert(0) += 1;
+            }
+            let most = counts.values().copied().max().unwrap_or(0);
+            if counts.get(&labels[node]) == Some(&most) {
+                continue;
+            }
+            let mut candidates: Vec<usize> = counts.iter().filter(|&(_, &c)| c == most).map(|(&l, _)| l).collect();
+            candidates.sort_unstable();
+            labels[node] = *candidates.choose(rng).unwrap_or(&labels[node]);
+            changed = true;
+        }
+        if !changed {
+            break;
+        }
+    }
+    (labels, passes)
+}
+
+fn entropy<'a>(counts: impl Iterator<Item = &'a usize>, total: f64) -> f64 {
+    counts
+        .map(|&count| count as f64 / total)
+        .filter(|&p| p > 0.0)
+        .map(|p| -p * p.ln())
+        .sum()
+}
+
+fn label_counts(labels: &[usize]) -> HashMap<usize, usize> {
+    let mut counts = HashMap::new();
+    for &label in labels {
+        *counts.entry(label).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn pair_counts(a: &[usize], b: &[usize]) -> HashMap<(usize, usize), usize> {
+    let mut counts = HashMap::new();
+    for pair in a.iter().copied().zip(b.iter().copied()) {
+        *counts.entry(pair).or_insert(0) += 1;
+    }
+    counts
+}
+
+// Mutual information divided by the mean of the two entropies; 1 when both labelings
+// are a single class
+pub fn normalized_mutual_information(a: &[usize], b: &[usize]) -> f64 {
+    let total = a.len() as f64;
+    if a.is_empty() {
+        return 1.0;
+    }
+    let (a_counts, b_counts) = (label_counts(a), label_counts(b));
+    let entropy_sum = entropy(a_counts.values(), total) + entropy(b_counts.values(), total);
+    if entropy_sum == 0.0 {
+        return 1.0;
+    }
+    let mutual_information: f64 = pair_counts(a, b)
+        .iter()
+        .map(|(&(x, y), &count)| {
+            let joint = count as f64 / total;
+            joint * (joint * total * total / (a_counts[&x] * b_counts[&y]) as f64).ln()
+        })
+        .sum();
+    2.0 * mutual_information / entropy_sum
+}
+
+// Hubert and Arabie's adjusted Rand index: 1 for identical partitions, about 0 for
+// independent ones
+pub fn adjusted_rand_index(a: &[usize], b: &[usize]) -> f64 {
+    let pairs = |n: usize| (n * n.saturating_sub(1) / 2) as f64;
+    let total_pairs = pairs(a.len());
+    if total_pairs == 0.0 {
+        return 1.0;
+    }
+    let joint: f64 = pair_counts(a, b).values().map(|&n| pairs(n)).sum();
+    let a_pairs: f64 = label_counts(a).values().map(|&n| pairs(n)).sum();
+    let b_pairs: f64 = label_counts(b).values().map(|&n| pairs(n)).sum();
+    let expected = a_pairs * b_pairs / total_pairs;
+    let max = (a_pairs + b_pairs) / 2.0;
+    if max == expected {
+        return 1.0;
+    }
+    (joint - expected) / (max - expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::group_graph;
+
+    #[test]
+    fn test_detection_recovers_groups() {
+        // Two 4-cliques, Book and Music, joined by a single 3 -> 4 edge
+        let groups = ["Book", "Book", "Book", "Book", "Music", "Music", "Music", "Music"];
+        let mut edges = Vec::new();
+        for clique in [0..4, 4..8] {
+            for a in clique.clone() {
+                edges.extend((a + 1..clique.end).map(|b| (a, b)));
+            }
+        }
+        edges.push((3, 4));
+        let graph = group_graph(&groups, &edges);
+
+        for method in [CommunityMethod::Louvain, CommunityMethod::LabelPropagation] {
+            let communities = detect_communities(&graph, method, 7);
+            assert_eq!(communities.community_count, 2, "{:?}", method);
+            assert!((communities.modularity - (12.0 / 13.0 - 0.5)).abs() < 1e-12);
+            assert!((communities.nmi - 1.0).abs() < 1e-12);
+            assert!((communities.ari - 1.0).abs() < 1e-12);
+            assert_eq!(communities.sizes.iter().map(|s| s.size).collect::<Vec<_>>(), vec![4, 4]);
+            assert_eq!(communities.assignments[0].community, 0);
+            assert_eq!(communities.contingency.len(), 2);
+            assert_eq!(communities, detect_communities(&graph, method, 7));
+        }
+    }
+
+    #[test]
+    fn test_partition_agreement_scores() {
+        assert!(normalized_mutual_information(&[0, 0, 1, 1], &[0, 1, 0, 1]).abs() < 1e-12);
+        assert!((adjusted_rand_index(&[0, 0, 1, 1], &[0, 1, 0, 1]) + 0.5).abs() < 1e-12);
+        assert!((adjusted_rand_index(&[0, 0, 1, 1], &[5, 5, 2, 2]) - 1.0).abs() < 1e-12);
+    }
+}
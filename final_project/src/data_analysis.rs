@@ -1,7 +1,34 @@
-use petgraph::graph::Graph;
-use std::collections::HashMap;
+use crate::asin_index::AsinIndex;
+use crate::data_processing::AmazonDataCleaner;
+use crate::product_graph::{GroupedNode, ProductGraph};
+use crate::sampling::seeded_rng;
+use crate::statistics::{quantile, DescriptiveStats};
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use rand::seq::{index, SliceRandom};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 
-pub fn calculate_average_degree_centrality(graph: &Graph<(u32, String), ()>) -> f64 {
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryCentrality {
+    pub category: String,
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub avg_degree_centrality: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoPurchaseRatio {
+    pub category: String,
+    pub in_category_ratio: f64,
+    pub cross_category_ratio: f64,
+}
+
+pub fn calculate_average_degree_centrality<N, E>(graph: &Graph<N, E>) -> f64 {
     let total_nodes = graph.node_count();
     if total_nodes == 0 {
         return 0.0; 
@@ -14,11 +41,1286 @@ pub fn calculate_average_degree_centrality(graph: &Graph<(u32, String), ()>) ->
     total_degree as f64 / total_nodes as f64
 }
 
+// In-, out- and total degree of one node. The `*_centrality` fields are the degrees
+// divided by (n - 1) when normalization was asked for, and the raw degrees otherwise.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeDegree {
+    pub node: usize,
+    pub product_id: u32,
+    pub group: String,
+    pub in_degree: usize,
+    pub out_degree: usize,
+    pub total_degree: usize,
+    pub in_centrality: f64,
+    pub out_centrality: f64,
+    pub total_centrality: f64,
+}
+
+// How many nodes have a given degree, for one of "in", "out" or "total"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DegreeFrequency {
+    pub measure: &'static str,
+    pub degree: usize,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DegreeCentrality {
+    pub node_count: usize,
+    pub normalized: bool,
+    pub nodes: Vec<NodeDegree>,
+    pub in_degree_stats: DescriptiveStats,
+    pub out_degree_stats: DescriptiveStats,
+    pub total_degree_stats: DescriptiveStats,
+    pub distribution: Vec<DegreeFrequency>,
+    pub top_in: Vec<NodeDegree>,
+    pub top_out: Vec<NodeDegree>,
+    pub top_total: Vec<NodeDegree>,
+}
+
+pub fn calculate_degree_centrality<N: GroupedNode, E>(
+    graph: &Graph<N, E>,
+    normalize: bool,
+    top_k: usize,
+) -> DegreeCentrality {
+    let node_count = graph.node_count();
+    let scale = if normalize && node_count > 1 { 1.0 / (node_count - 1) as f64 } else { 1.0 };
+
+    let nodes: Vec<NodeDegree> = graph
+        .node_indices()
+        .map(|node| {
+            let in_degree = graph.neighbors_directed(node, Direction::Incoming).count();
+            let out_degree = graph.neighbors_directed(node, Direction::Outgoing).count();
+            let total_degree = in_degree + out_degree;
+            NodeDegree {
+                node: node.index(),
+                product_id: graph[node].product_id(),
+                group: graph[node].group().to_string(),
+                in_degree,
+                out_degree,
+                total_degree,
+                in_centrality: in_degree as f64 * scale,
+                out_centrality: out_degree as f64 * scale,
+                total_centrality: total_degree as f64 * scale,
+            }
+        })
+        .collect();
+
+    let stats = |degree: fn(&NodeDegree) -> usize| {
+        DescriptiveStats::from_values(nodes.iter().map(|n| degree(n) as f64).collect(), 0)
+    };
+    let frequencies = |measure: &'static str, degree: fn(&NodeDegree) -> usize| {
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for node in &nodes {
+            *counts.entry(degree(node)).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .map(move |(degree, count)| DegreeFrequency { measure, degree, count })
+    };
+    // Highest degree first, ties by node index
+    let top = |degree: fn(&NodeDegree) -> usize| {
+        let mut ranked: Vec<&NodeDegree> = nodes.iter().collect();
+        ranked.sort_by(|a, b| degree(b).cmp(&degree(a)).then(a.node.cmp(&b.node)));
+        ranked.into_iter().take(top_k).cloned().collect::<Vec<_>>()
+    };
+
+    let in_degree: fn(&NodeDegree) -> usize = |n| n.in_degree;
+    let out_degree: fn(&NodeDegree) -> usize = |n| n.out_degree;
+    let total_degree: fn(&NodeDegree) -> usize = |n| n.total_degree;
+
+    DegreeCentrality {
+        node_count,
+        normalized: normalize,
+        in_degree_stats: stats(in_degree),
+        out_degree_stats: stats(out_degree),
+        total_degree_stats: stats(total_degree),
+        distribution: frequencies("in", in_degree)
+            .chain(frequencies("out", out_degree))
+            .chain(frequencies("total", total_degree))
+            .collect(),
+        top_in: top(in_degree),
+        top_out: top(out_degree),
+        top_total: top(total_degree),
+        nodes,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PageRankOptions {
+    pub damping: f64,
+    // Iteration stops once the L1 change between two iterations drops below this
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for PageRankOptions {
+    fn default() -> Self {
+        PageRankOptions {
+            damping: 0.85,
+            tolerance: 1e-10,
+            max_iterations: 100,
+        }
+    }
+}
+
+// How a power iteration ended. `residual` is the L1 change made by the last iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Convergence {
+    pub iterations: usize,
+    pub converged: bool,
+    pub residual: f64,
+}
+
+// Apply `step` to `scores` until the L1 change drops below `tolerance` or the
+// iteration cap is reached
+fn power_iterate(
+    mut scores: Vec<f64>,
+    tolerance: f64,
+    max_iterations: usize,
+    mut step: impl FnMut(&[f64]) -> Vec<f64>,
+) -> (Vec<f64>, Convergence) {
+    let mut iterations = 0;
+    let mut residual = if scores.is_empty() { 0.0 } else { f64::INFINITY };
+
+    while iterations < max_iterations && residual >= tolerance {
+        let next = step(&scores);
+        residual = next.iter().zip(&scores).map(|(a, b)| (a - b).abs()).sum();
+        scores = next;
+        iterations += 1;
+    }
+
+    let convergence = Convergence {
+        iterations,
+        converged: residual < tolerance,
+        residual,
+    };
+    (scores, convergence)
+}
+
+// Scale `scores` so they sum to 1, leaving an all-zero vector alone
+fn normalize_sum(mut scores: Vec<f64>) -> Vec<f64> {
+    let total: f64 = scores.iter().sum();
+    if total > 0.0 {
+        scores.iter_mut().for_each(|score| *score /= total);
+    }
+    scores
+}
+
+// `scores[i]` is the score of node `i`; scores sum to 1
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageRankResult {
+    pub scores: Vec<f64>,
+    pub convergence: Convergence,
+}
+
+impl PageRankResult {
+    pub fn score(&self, node: NodeIndex) -> Option<f64> {
+        self.scores.get(node.index()).copied()
+    }
+
+    // Highest scores first, ties by node index
+    pub fn top_k(&self, k: usize) -> Vec<(NodeIndex, f64)> {
+        top_k_scores(&self.scores, k)
+    }
+}
+
+pub fn top_k_scores(scores: &[f64], k: usize) -> Vec<(NodeIndex, f64)> {
+    let mut ranked: Vec<(NodeIndex, f64)> = scores
+        .iter()
+        .enumerate()
+        .map(|(i, &score)| (NodeIndex::new(i), score))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(k);
+    ranked
+}
+
+pub fn calculate_pagerank<N, E>(graph: &Graph<N, E>, options: &PageRankOptions) -> PageRankResult {
+    calculate_personalized_pagerank(graph, options, &[])
+}
+
+// PageRank whose random jumps (and the mass of nodes without out-edges) land only on
+// `seeds`. With no seeds this is plain PageRank.
+pub fn calculate_personalized_pagerank<N, E>(
+    graph: &Graph<N, E>,
+    options: &PageRankOptions,
+    seeds: &[NodeIndex],
+) -> PageRankResult {
+    let node_count = graph.node_count();
+    let mut teleport = vec![0.0; node_count];
+    if seeds.is_empty() {
+        teleport.fill(1.0 / node_count as f64);
+    } else {
+        for seed in seeds {
+            teleport[seed.index()] += 1.0 / seeds.len() as f64;
+        }
+    }
+
+    let out_degree: Vec<usize> = graph
+        .node_indices()
+        .map(|node| graph.edges_directed(node, Direction::Outgoing).count())
+        .collect();
+    let damping = options.damping;
+
+    let (scores, convergence) = power_iterate(teleport.clone(), options.tolerance, options.max_iterations, |scores| {
+        let dangling_mass: f64 = (0..node_count).filter(|&i| out_degree[i] == 0).map(|i| scores[i]).sum();
+        let mut next: Vec<f64> = teleport
+            .iter()
+            .map(|&t| (damping * dangling_mass + 1.0 - damping) * t)
+            .collect();
+        for edge in graph.edge_references() {
+            let source = edge.source().index();
+            next[edge.target().index()] += damping * scores[source] / out_degree[source] as f64;
+        }
+        next
+    });
+
+    PageRankResult { scores, convergence }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PowerIterationOptions {
+    // Iteration stops once the L1 change between two iterations drops below this
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for PowerIterationOptions {
+    fn default() -> Self {
+        PowerIterationOptions {
+            tolerance: 1e-10,
+            max_iterations: 100,
+        }
+    }
+}
+
+// Scores sum to 1
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EigenvectorResult {
+    pub scores: Vec<f64>,
+    pub convergence: Convergence,
+}
+
+// A product is central when products that are central list it as similar. Iterates
+// with A + I rather than A, which has the same leading eigenvector but also converges
+// on graphs with cycles of even length or no cycles at all.
+pub fn calculate_eigenvector_centrality<N, E>(
+    graph: &Graph<N, E>,
+    options: &PowerIterationOptions,
+) -> EigenvectorResult {
+    let node_count = graph.node_count();
+    let initial = vec![1.0 / node_count as f64; node_count];
+    let (scores, convergence) = power_iterate(initial, options.tolerance, options.max_iterations, |scores| {
+        let mut next = scores.to_vec();
+        for edge in graph.edge_references() {
+            next[edge.target().index()] += scores[edge.source().index()];
+        }
+        normalize_sum(next)
+    });
+    EigenvectorResult { scores, convergence }
+}
+
+// Hubs list good authorities as similar; authorities are listed by good hubs. Each
+// score vector sums to 1.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HitsResult {
+    pub hubs: Vec<f64>,
+    pub authorities: Vec<f64>,
+    pub convergence: Convergence,
+}
+
+pub fn calculate_hits<N, E>(graph: &Graph<N, E>, options: &PowerIterationOptions) -> HitsResult {
+    let node_count = graph.node_count();
+    let authorities_of = |hubs: &[f64]| {
+        let mut authorities = vec![0.0; node_count];
+        for edge in graph.edge_references() {
+            authorities[edge.target().index()] += hubs[edge.source().index()];
+        }
+        normalize_sum(authorities)
+    };
+
+    // Iterate on the hub vector alone; the authorities follow from the final hubs
+    let initial = vec![1.0 / node_count as f64; node_count];
+    let (hubs, convergence) = power_iterate(initial, options.tolerance, options.max_iterations, |hubs| {
+        let authorities = authorities_of(hubs);
+        let mut next = vec![0.0; node_count];
+        for edge in graph.edge_references() {
+            next[edge.source().index()] += authorities[edge.target().index()];
+        }
+        normalize_sum(next)
+    });
+
+    HitsResult {
+        authorities: authorities_of(&hubs),
+        hubs,
+        convergence,
+    }
+}
+
+// Personalization seeds: the graph nodes of the given ASINs, ignoring unknown ones
+pub fn seed_nodes_by_asin(graph: &ProductGraph, asins: &[String]) -> Vec<NodeIndex> {
+    let mut wanted = AsinIndex::default();
+    for (position, asin) in asins.iter().enumerate() {
+        wanted.insert(asin, position);
+    }
+    graph
+        .node_indices()
+        .filter(|&node| {
+            graph[node]
+                .asin
+                .as_deref()
+                .is_some_and(|asin| wanted.position(asin).is_some())
+        })
+        .collect()
+}
+
+// Personalization seeds: every node in `group`
+pub fn seed_nodes_by_group<N: GroupedNode, E>(graph: &Graph<N, E>, group: &str) -> Vec<NodeIndex> {
+    graph.node_indices().filter(|&node| graph[node].group() == group).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCentralityMode {
+    // Brandes' algorithm with a BFS from every node; fine for per-category graphs
+    Exact,
+    // BFS from a random subset of pivot nodes only. The pivot count is chosen so that,
+    // with probability at least 1 - delta, every betweenness and harmonic score is
+    // within `epsilon` (times n / (n - 1)) of its exact value.
+    Approximate { epsilon: f64, delta: f64, seed: u64 },
+}
+
+// Betweenness is normalized by (n - 1)(n - 2), the number of ordered pairs a node can
+// sit between. Closeness is the Wasserman-Faust variant, scaled by the share of nodes
+// reachable, so graphs that are not strongly connected still give comparable scores.
+// Both closeness and harmonic centrality follow outgoing `similar` links.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodePathCentrality {
+    pub node: usize,
+    pub product_id: u32,
+    pub group: String,
+    pub betweenness: f64,
+    pub closeness: f64,
+    pub harmonic: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathCentrality {
+    pub node_count: usize,
+    pub exact: bool,
+    pub pivots: usize,
+    pub error_bound: Option<f64>,
+    pub nodes: Vec<NodePathCentrality>,
+    pub betweenness_stats: DescriptiveStats,
+    pub closeness_stats: DescriptiveStats,
+    pub harmonic_stats: DescriptiveStats,
+    pub top_betweenness: Vec<NodePathCentrality>,
+    pub top_closeness: Vec<NodePathCentrality>,
+    pub top_harmonic: Vec<NodePathCentrality>,
+}
+
+// Hoeffding's bound with a union bound over all n nodes
+pub fn pivot_count(node_count: usize, epsilon: f64, delta: f64) -> usize {
+    if node_count == 0 {
+        return 0;
+    }
+    let pivots = ((2.0 * node_count as f64 / delta).ln() / (2.0 * epsilon * epsilon)).ceil();
+    (pivots as usize).clamp(1, node_count)
+}
+
+// Out- and in-neighbour lists without parallel edges or self-loops, which would
+// otherwise count as extra shortest paths
+fn simple_adjacency<N, E>(graph: &Graph<N, E>, direction: Direction) -> Vec<Vec<usize>> {
+    graph
+        .node_indices()
+        .map(|node| {
+            let mut neighbors: Vec<usize> = graph
+                .neighbors_directed(node, direction)
+                .filter(|&neighbor| neighbor != node)
+                .map(|neighbor| neighbor.index())
+                .collect();
+            neighbors.sort_unstable();
+            neighbors.dedup();
+            neighbors
+        })
+        .collect()
+}
+
+// Unweighted single-source shortest paths: hop distances, number of shortest paths and
+// the reached nodes in BFS order
+struct ShortestPaths {
+    distance: Vec<Option<usize>>,
+    path_count: Vec<f64>,
+    order: Vec<usize>,
+}
+
+fn shortest_paths_from(adjacency: &[Vec<usize>], source: usize) -> ShortestPaths {
+    let mut distance = vec![None; adjacency.len()];
+    let mut path_count = vec![0.0; adjacency.len()];
+    let mut order = Vec::new();
+    let mut queue = VecDeque::from([source]);
+    distance[source] = Some(0);
+    path_count[source] = 1.0;
+
+    while let Some(node) = queue.pop_front() {
+        order.push(node);
+        let next = distance[node].map(|d| d + 1);
+        for &neighbor in &adjacency[node] {
+            if distance[neighbor].is_none() {
+                distance[neighbor] = next;
+                queue.push_back(neighbor);
+            }
+            if distance[neighbor] == next {
+                path_count[neighbor] += path_count[node];
+            }
+        }
+    }
+
+    ShortestPaths { distance, path_count, order }
+}
+
+// Brandes' dependency accumulation: adds to `betweenness` how much each node lies on
+// shortest paths out of the BFS source
+fn accumulate_dependencies(paths: &ShortestPaths, in_adjacency: &[Vec<usize>], betweenness: &mut [f64]) {
+    let mut dependency = vec![0.0; in_adjacency.len()];
+    for &node in paths.order.iter().rev() {
+        let Some(distance) = paths.distance[node] else { continue };
+        for &predecessor in &in_adjacency[node] {
+            if paths.distance[predecessor].map(|d| d + 1) == Some(distance) {
+                dependency[predecessor] +=
+                    paths.path_count[predecessor] / paths.path_count[node] * (1.0 + dependency[node]);
+            }
+        }
+        if distance > 0 {
+            betweenness[node] += dependency[node];
+        }
+    }
+}
+
+// Per-node sums over BFS distances to other nodes: how many were reached, their total
+// distance and the sum of inverse distances
+#[derive(Clone, Copy, Default)]
+struct DistanceSums {
+    reached: f64,
+    total: f64,
+    inverse: f64,
+}
+
+impl DistanceSums {
+    fn add(&mut self, distance: usize) {
+        self.reached += 1.0;
+        self.total += distance as f64;
+        self.inverse += 1.0 / distance as f64;
+    }
+}
+
+pub fn calculate_path_centrality<N: GroupedNode, E>(
+    graph: &Graph<N, E>,
+    mode: PathCentralityMode,
+    top_k: usize,
+) -> PathCentrality {
+    let node_count = graph.node_count();
+    let out_adjacency = simple_adjacency(graph, Direction::Outgoing);
+    let in_adjacency = simple_adjacency(graph, Direction::Incoming);
+
+    // Falls back to the exact computation when the bound needs every node as a pivot
+    let pivots = match mode {
+        PathCentralityMode::Exact => None,
+        PathCentralityMode::Approximate { epsilon, delta, seed } => {
+            let count = pivot_count(node_count, epsilon, delta);
+            (count < node_count).then(|| (index::sample(&mut seeded_rng(seed), node_count, count).into_vec(), epsilon))
+        }
+    };
+
+    let mut betweenness = vec![0.0; node_count];
+    let mut sums = vec![DistanceSums::default(); node_count];
+    // Each source's contribution is scaled up by n / sources to estimate the full sum
+    let sources = match &pivots {
+        None => {
+            for (source, source_sums) in sums.iter_mut().enumerate() {
+                let paths = shortest_paths_from(&out_adjacency, source);
+                accumulate_dependencies(&paths, &in_adjacency, &mut betweenness);
+                for &node in &paths.order[1..] {
+                    source_sums.add(paths.distance[node].unwrap_or_default());
+                }
+            }
+            node_count
+        }
+        Some((pivots, _)) => {
+            for &pivot in pivots {
+                let paths = shortest_paths_from(&out_adjacency, pivot);
+                accumulate_dependencies(&paths, &in_adjacency, &mut betweenness);
+                // Distances *to* the pivot, for closeness along outgoing links
+                let reverse = shortest_paths_from(&in_adjacency, pivot);
+                for &node in &reverse.order[1..] {
+                    sums[node].add(reverse.distance[node].unwrap_or_default());
+                }
+            }
+            pivots.len()
+        }
+    };
+
+    let scale = if sources == 0 { 0.0 } else { node_count as f64 / sources as f64 };
+    let others = node_count.saturating_sub(1) as f64;
+    let pairs = others * node_count.saturating_sub(2) as f64;
+    let nodes: Vec<NodePathCentrality> = graph
+        .node_indices()
+        .map(|node| {
+            let i = node.index();
+            let sum = sums[i];
+            NodePathCentrality {
+                node: i,
+                product_id: graph[node].product_id(),
+                group: graph[node].group().to_string(),
+                betweenness: if pairs > 0.0 { betweenness[i] * scale / pairs } else { 0.0 },
+                closeness: if sum.total > 0.0 {
+                    (sum.reached * scale / others) * (sum.reached / sum.total)
+                } else {
+                    0.0
+                },
+                harmonic: if others > 0.0 { sum.inverse * scale / others } else { 0.0 },
+            }
+        })
+        .collect();
+
+    let stats = |score: fn(&NodePathCentrality) -> f64| {
+        DescriptiveStats::from_values(nodes.iter().map(score).collect(), 0)
+    };
+    // Highest score first, ties by node index
+    let top = |score: fn(&NodePathCentrality) -> f64| {
+        let mut ranked: Vec<&NodePathCentrality> = nodes.iter().collect();
+        ranked.sort_by(|a, b| score(b).total_cmp(&score(a)).then(a.node.cmp(&b.node)));
+        ranked.into_iter().take(top_k).cloned().collect::<Vec<_>>()
+    };
+
+    let betweenness: fn(&NodePathCentrality) -> f64 = |n| n.betweenness;
+    let closeness: fn(&NodePathCentrality) -> f64 = |n| n.closeness;
+    let harmonic: fn(&NodePathCentrality) -> f64 = |n| n.harmonic;
+
+    PathCentrality {
+        node_count,
+        exact: pivots.is_none(),
+        pivots: sources,
+        error_bound: pivots.map(|(_, epsilon)| epsilon * node_count as f64 / others),
+        betweenness_stats: stats(betweenness),
+        closeness_stats: stats(closeness),
+        harmonic_stats: stats(harmonic),
+        top_betweenness: top(betweenness),
+        top_closeness: top(closeness),
+        top_harmonic: top(harmonic),
+        nodes,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Connectivity {
+    // Connected when linked either way, ignoring edge direction
+    #[default]
+    Weak,
+    // Connected when each can reach the other along similar links
+    Strong,
+}
+
+// How many components have a given number of nodes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentSize {
+    pub size: usize,
+    pub count: usize,
+}
+
+// Components are numbered largest first (ties by lowest node index), so component 0 is
+// the giant component
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComponentAnalysis {
+    pub connectivity: Connectivity,
+    pub node_count: usize,
+    pub component_count: usize,
+    pub giant_size: usize,
+    pub giant_share: f64,
+    pub size_stats: DescriptiveStats,
+    pub size_distribution: Vec<ComponentSize>,
+    // `membership[i]` is the component of node i
+    pub membership: Vec<usize>,
+    #[serde(skip)]
+    pub components: Vec<Vec<NodeIndex>>,
+}
+
+impl ComponentAnalysis {
+    pub fn giant_component(&self) -> &[NodeIndex] {
+        self.components.first().map_or(&[], Vec::as_slice)
+    }
+}
+
+pub fn analyze_components<N, E>(graph: &Graph<N, E>, connectivity: Connectivity) -> ComponentAnalysis {
+    let node_count = graph.node_count();
+    let mut components: Vec<Vec<NodeIndex>> = match connectivity {
+        Connectivity::Weak => {
+            let mut union_find = UnionFind::new(node_count);
+            for edge in graph.edge_references() {
+                union_find.union(edge.source().index(), edge.target().index());
+            }
+            let mut by_root: BTreeMap<usize, Vec<NodeIndex>> = BTreeMap::new();
+            for node in graph.node_indices() {
+                by_root.entry(union_find.find(node.index())).or_default().push(node);
+            }
+            by_root.into_values().collect()
+        }
+        Connectivity::Strong => tarjan_scc(graph),
+    };
+    for component in &mut components {
+        component.sort_unstable();
+    }
+    components.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+
+    let mut membership = vec![0; node_count];
+    let mut sizes: BTreeMap<usize, usize> = BTreeMap::new();
+    for (id, component) in components.iter().enumerate() {
+        for node in component {
+            membership[node.index()] = id;
+        }
+        *sizes.entry(component.len()).or_insert(0) += 1;
+    }
+    let giant_size = components.first().map_or(0, Vec::len);
+
+    ComponentAnalysis {
+        connectivity,
+        node_count,
+        component_count: components.len(),
+        giant_size,
+        giant_share: if node_count == 0 { 0.0 } else { giant_size as f64 / node_count as f64 },
+        size_stats: DescriptiveStats::from_values(components.iter().map(|c| c.len() as f64).collect(), 0),
+        size_distribution: sizes.into_iter().map(|(size, count)| ComponentSize { size, count }).collect(),
+        membership,
+        components,
+    }
+}
+
+// The subgraph on `nodes` with every edge between them. Nodes are renumbered but keep
+// their relative order.
+pub fn induced_subgraph<N: Clone, E: Clone>(graph: &Graph<N, E>, nodes: &[NodeIndex]) -> Graph<N, E> {
+    let mut keep = vec![false; graph.node_count()];
+    for node in nodes {
+        keep[node.index()] = true;
+    }
+    graph.filter_map(
+        |node, weight| keep[node.index()].then(|| weight.clone()),
+        |_, weight| Some(weight.clone()),
+    )
+}
+
+pub fn extract_giant_component<N: Clone, E: Clone>(graph: &Graph<N, E>, connectivity: Connectivity) -> Graph<N, E> {
+    induced_subgraph(graph, analyze_components(graph, connectivity).giant_component())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryComponents {
+    pub category: String,
+    pub node_count: usize,
+    pub component_count: usize,
+    pub giant_size: usize,
+    pub giant_share: f64,
+}
+
+impl CategoryComponents {
+    pub fn from_analysis(category: &str, analysis: &ComponentAnalysis) -> Self {
+        CategoryComponents {
+            category: category.to_string(),
+            node_count: analysis.node_count,
+            component_count: analysis.component_count,
+            giant_size: analysis.giant_size,
+            giant_share: analysis.giant_share,
+        }
+    }
+}
+
+// Neighbours ignoring edge direction, without duplicates or self-loops
+pub(crate) fn undirected_adjacency<N, E>(graph: &Graph<N, E>) -> Vec<Vec<usize>> {
+    let mut adjacency = simple_adjacency(graph, Direction::Outgoing);
+    for (neighbors, incoming) in adjacency.iter_mut().zip(simple_adjacency(graph, Direction::Incoming)) {
+        neighbors.extend(incoming);
+        neighbors.sort_unstable();
+        neighbors.dedup();
+    }
+    adjacency
+}
+
+// Triangles through a node and its local clustering coefficient, both on the
+// undirected view of the graph. Nodes with fewer than two neighbours have coefficient 0.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeClustering {
+    pub node: usize,
+    pub product_id: u32,
+    pub group: String,
+    pub degree: usize,
+    pub triangles: usize,
+    pub clustering: f64,
+}
+
+// `triangle_corners` sums the triangles through each of the category's nodes, so a
+// triangle inside the category counts three times; `transitivity` is closed over
+// connected triples centred on the category's nodes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryClustering {
+    pub category: String,
+    pub node_count: usize,
+    pub triangle_corners: usize,
+    pub avg_clustering: f64,
+    pub transitivity: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Clustering {
+    pub node_count: usize,
+    pub triangle_count: usize,
+    pub avg_clustering: f64,
+    pub transitivity: f64,
+    pub nodes: Vec<NodeClustering>,
+    pub categories: Vec<CategoryClustering>,
+}
+
+impl Clustering {
+    // The whole graph as a single category row; its `triangle_corners` is three
+    // times `triangle_count`
+    pub fn overall(&self, label: &str) -> CategoryClustering {
+        summarize_clustering(label.to_string(), self.nodes.iter())
+    }
+}
+
+fn summarize_clustering<'a>(category: String, nodes: impl Iterator<Item = &'a NodeClustering>) -> CategoryClustering {
+    let (mut node_count, mut triangle_corners, mut clustering_sum, mut triples) = (0, 0, 0.0, 0);
+    for node in nodes {
+        node_count += 1;
+        triangle_corners += node.triangles;
+        clustering_sum += node.clustering;
+        triples += node.degree * node.degree.saturating_sub(1) / 2;
+    }
+    CategoryClustering {
+        category,
+        node_count,
+        triangle_corners,
+        avg_clustering: if node_count == 0 { 0.0 } else { clustering_sum / node_count as f64 },
+        // Over the whole graph this is 3T / triples
+        transitivity: if triples == 0 { 0.0 } else { triangle_corners as f64 / triples as f64 },
+    }
+}
+
+pub fn calculate_clustering<N: GroupedNode, E>(graph: &Graph<N, E>) -> Clustering {
+    let adjacency = undirected_adjacency(graph);
+    let mut triangles = vec![0; adjacency.len()];
+
+    // Each triangle u < v < w is found once, from its lowest node
+    let mut triangle_count = 0;
+    for (u, neighbors) in adjacency.iter().enumerate() {
+        for &v in neighbors.iter().filter(|&&v| v > u) {
+            for &w in adjacency[v].iter().filter(|&&w| w > v) {
+                if neighbors.binary_search(&w).is_ok() {
+                    triangle_count += 1;
+                    triangles[u] += 1;
+                    triangles[v] += 1;
+                    triangles[w] += 1;
+                }
+            }
+        }
+    }
+
+    let nodes: Vec<NodeClustering> = graph
+        .node_indices()
+        .map(|node| {
+            let i = node.index();
+            let degree = adjacency[i].len();
+            let pairs = degree * degree.saturating_sub(1) / 2;
+            NodeClustering {
+                node: i,
+                product_id: graph[node].product_id(),
+                group: graph[node].group().to_string(),
+                degree,
+                triangles: triangles[i],
+                clustering: if pairs == 0 { 0.0 } else { triangles[i] as f64 / pairs as f64 },
+            }
+        })
+        .collect();
+
+    let overall = summarize_clustering(String::new(), nodes.iter());
+    let mut by_group: BTreeMap<&str, Vec<&NodeClustering>> = BTreeMap::new();
+    for node in &nodes {
+        by_group.entry(node.group.as_str()).or_default().push(node);
+    }
+    let categories = by_group
+        .into_iter()
+        .map(|(group, members)| summarize_clustering(group.to_string(), members.into_iter()))
+        .collect();
+
+    Clustering {
+        node_count: nodes.len(),
+        triangle_count,
+        avg_clustering: overall.avg_clustering,
+        transitivity: overall.transitivity,
+        nodes,
+        categories,
+    }
+}
+
+// One cell of a directed mixing matrix. `probability` is the share of the source
+// label's edges that go to the target label, and `expected` the count a random graph
+// with the same degrees would give.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MixingCell {
+    pub source: String,
+    pub target: String,
+    pub count: f64,
+    pub probability: f64,
+    pub expected: f64,
+}
+
+// Edges between labelled nodes, by source and target label. A node with several labels
+// (several category paths) splits each of its edges evenly between them, so the counts
+// still add up to `edge_count`. Edges touching an unlabelled node are only counted in
+// `unlabelled_edges`. `assortativity` is Newman's coefficient: 1 when every edge stays
+// inside its label, 0 when edges ignore labels; undefined with a single label.
+// `cells` are sorted by source, then target, and only hold label pairs with edges
+// unless unobserved pairs were asked for.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MixingMatrix {
+    pub labels: Vec<String>,
+    pub edge_count: usize,
+    pub unlabelled_edges: usize,
+    pub assortativity: Option<f64>,
+    pub cells: Vec<MixingCell>,
+}
+
+impl MixingMatrix {
+    pub fn cell(&self, source: &str, target: &str) -> Option<&MixingCell> {
+        self.cells
+            .binary_search_by(|cell| (cell.source.as_str(), cell.target.as_str()).cmp(&(source, target)))
+            .ok()
+            .map(|i| &self.cells[i])
+    }
+}
+
+// `node_labels[i]` are the labels of node i. With `include_unobserved`, label pairs
+// without edges get a cell too, for their expected count; that is quadratic in the
+// number of labels.
+pub fn calculate_mixing_matrix<N, E>(
+    graph: &Graph<N, E>,
+    node_labels: &[Vec<String>],
+    include_unobserved: bool,
+) -> MixingMatrix {
+    let labels: Vec<String> = node_labels
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let position: HashMap<&str, usize> = labels.iter().enumerate().map(|(i, l)| (l.as_str(), i)).collect();
+    let size = labels.len();
+
+    // Sparse, since most label pairs never share an edge at deeper category levels
+    let mut counts: BTreeMap<(usize, usize), f64> = BTreeMap::new();
+    let mut row_sums = vec![0.0; size];
+    let mut column_sums = vec![0.0; size];
+    let (mut edge_count, mut unlabelled_edges) = (0, 0);
+    for edge in graph.edge_references() {
+        let sources = &node_labels[edge.source().index()];
+        let targets = &node_labels[edge.target().index()];
+        if sources.is_empty() || targets.is_empty() {
+            unlabelled_edges += 1;
+            continue;
+        }
+        edge_count += 1;
+        let weight = 1.0 / (sources.len() * targets.len()) as f64;
+        for source in sources {
+            for target in targets {
+                let (i, j) = (position[source.as_str()], position[target.as_str()]);
+                *counts.entry((i, j)).or_insert(0.0) += weight;
+                row_sums[i] += weight;
+                column_sums[j] += weight;
+            }
+        }
+    }
+
+    let total = edge_count as f64;
+    let cell = |i: usize, j: usize, count: f64| MixingCell {
+        source: labels[i].clone(),
+        target: labels[j].clone(),
+        count,
+        probability: if row_sums[i] > 0.0 { count / row_sums[i] } else { 0.0 },
+        // The configuration model joins out-stubs of i to in-stubs of j at random
+        expected: if total > 0.0 { row_sums[i] * column_sums[j] / total } else { 0.0 },
+    };
+    let cells: Vec<MixingCell> = if include_unobserved {
+        (0..size)
+            .flat_map(|i| (0..size).map(move |j| (i, j)))
+            .filter(|&(i, j)| counts.contains_key(&(i, j)) || (row_sums[i] > 0.0 && column_sums[j] > 0.0))
+            .map(|(i, j)| cell(i, j, counts.get(&(i, j)).copied().unwrap_or(0.0)))
+            .collect()
+    } else {
+        counts.iter().map(|(&(i, j), &count)| cell(i, j, count)).collect()
+    };
+
+    let assortativity = (total > 0.0).then(|| {
+        let diagonal: f64 = (0..size).map(|i| counts.get(&(i, i)).copied().unwrap_or(0.0) / total).sum();
+        let chance: f64 = (0..size).map(|i| row_sums[i] * column_sums[i] / (total * total)).sum();
+        (chance < 1.0).then(|| (diagonal - chance) / (1.0 - chance))
+    });
+
+    MixingMatrix {
+        labels,
+        edge_count,
+        unlabelled_edges,
+        assortativity: assortativity.flatten(),
+        cells,
+    }
+}
+
+// Mixing between the `group` of each node
+pub fn calculate_group_mixing_matrix<N: GroupedNode, E>(graph: &Graph<N, E>, include_unobserved: bool) -> MixingMatrix {
+    let labels: Vec<Vec<String>> = graph.node_weights().map(|node| vec![node.group().to_string()]).collect();
+    calculate_mixing_matrix(graph, &labels, include_unobserved)
+}
+
+// Pearson correlation of the pairs; None with fewer than two pairs or no variance
+fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for &(x, y) in pairs {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    (variance_x > 0.0 && variance_y > 0.0).then(|| covariance / (variance_x * variance_y).sqrt())
+}
+
+// The four directed degree pairings, as (name, source degree, target degree)
+const DEGREE_VARIANTS: [(&str, Direction, Direction); 4] = [
+    ("out-in", Direction::Outgoing, Direction::Incoming),
+    ("in-in", Direction::Incoming, Direction::Incoming),
+    ("out-out", Direction::Outgoing, Direction::Outgoing),
+    ("in-out", Direction::Incoming, Direction::Outgoing),
+];
+
+fn degrees<N, E>(graph: &Graph<N, E>, direction: Direction) -> Vec<usize> {
+    graph
+        .node_indices()
+        .map(|node| graph.neighbors_directed(node, direction).count())
+        .collect()
+}
+
+// Correlation across edges of a value at the source and at the target. `pairs` is how
+// many edges had a value at both ends.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AssortativityCoefficient {
+    pub measure: String,
+    pub pairs: usize,
+    pub coefficient: Option<f64>,
+}
+
+pub fn numeric_assortativity<N, E>(
+    graph: &Graph<N, E>,
+    measure: &str,
+    values: &[Option<f64>],
+) -> AssortativityCoefficient {
+    let pairs: Vec<(f64, f64)> = graph
+        .edge_references()
+        .filter_map(|edge| Some((values[edge.source().index()]?, values[edge.target().index()]?)))
+        .collect();
+    AssortativityCoefficient {
+        measure: measure.to_string(),
+        pairs: pairs.len(),
+        coefficient: pearson(&pairs),
+    }
+}
+
+// Degree assortativity for each pairing of source and target degree, named
+// "degree out-in" and so on
+pub fn calculate_degree_assortativity<N, E>(graph: &Graph<N, E>) -> Vec<AssortativityCoefficient> {
+    let in_degree = degrees(graph, Direction::Incoming);
+    let out_degree = degrees(graph, Direction::Outgoing);
+    let pick = |direction| if direction == Direction::Incoming { &in_degree } else { &out_degree };
+
+    DEGREE_VARIANTS
+        .iter()
+        .map(|&(name, source, target)| {
+            let pairs: Vec<(f64, f64)> = graph
+                .edge_references()
+                .map(|edge| {
+                    let x = pick(source)[edge.source().index()];
+                    let y = pick(target)[edge.target().index()];
+                    (x as f64, y as f64)
+                })
+                .collect();
+            AssortativityCoefficient {
+                measure: format!("degree {}", name),
+                pairs: pairs.len(),
+                coefficient: pearson(&pairs),
+            }
+        })
+        .collect()
+}
+
+// Mean target-side degree of the products listed by nodes whose source-side degree is
+// `degree`, averaged over those nodes; nodes listing nothing are left out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NeighborDegree {
+    pub variant: &'static str,
+    pub degree: usize,
+    pub node_count: usize,
+    pub avg_neighbor_degree: f64,
+}
+
+pub fn calculate_average_neighbor_degree<N, E>(graph: &Graph<N, E>) -> Vec<NeighborDegree> {
+    let in_degree = degrees(graph, Direction::Incoming);
+    let out_degree = degrees(graph, Direction::Outgoing);
+    let pick = |direction| if direction == Direction::Incoming { &in_degree } else { &out_degree };
+
+    let mut rows = Vec::new();
+    for &(variant, source, target) in &DEGREE_VARIANTS {
+        let mut by_degree: BTreeMap<usize, (usize, f64)> = BTreeMap::new();
+        for node in graph.node_indices() {
+            let neighbors: Vec<usize> = graph
+                .neighbors_directed(node, Direction::Outgoing)
+                .map(|neighbor| pick(target)[neighbor.index()])
+                .collect();
+            if neighbors.is_empty() {
+                continue;
+            }
+            let mean = neighbors.iter().sum::<usize>() as f64 / neighbors.len() as f64;
+            let entry = by_degree.entry(pick(source)[node.index()]).or_insert((0, 0.0));
+            entry.0 += 1;
+            entry.1 += mean;
+        }
+        rows.extend(by_degree.into_iter().map(|(degree, (node_count, total))| NeighborDegree {
+            variant,
+            degree,
+            node_count,
+            avg_neighbor_degree: total / node_count as f64,
+        }));
+    }
+    rows
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Assortativity {
+    pub coefficients: Vec<AssortativityCoefficient>,
+    pub neighbor_degree: Vec<NeighborDegree>,
+}
+
+// Follow `similar` links only, or treat every link as two-way
+fn traversal_adjacency<N, E>(graph: &Graph<N, E>, directed: bool) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+    if directed {
+        (simple_adjacency(graph, Direction::Outgoing), simple_adjacency(graph, Direction::Incoming))
+    } else {
+        let adjacency = undirected_adjacency(graph);
+        (adjacency.clone(), adjacency)
+    }
+}
+
+// Nodes of one shortest path from `source` to `target`, both included. Walking back
+// from the target, ties go to the lowest node index.
+pub fn shortest_path<N, E>(
+    graph: &Graph<N, E>,
+    source: NodeIndex,
+    target: NodeIndex,
+    directed: bool,
+) -> Option<Vec<NodeIndex>> {
+    let (adjacency, reverse) = traversal_adjacency(graph, directed);
+    let distance = shortest_paths_from(&adjacency, source.index()).distance;
+    let mut node = target.index();
+    let mut path = vec![node];
+    let mut remaining = distance[node]?;
+    while remaining > 0 {
+        remaining -= 1;
+        node = *reverse[node].iter().find(|&&p| distance[p] == Some(remaining))?;
+        path.push(node);
+    }
+    path.reverse();
+    Some(path.into_iter().map(NodeIndex::new).collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathStep {
+    pub hop: usize,
+    pub node: usize,
+    pub asin: Option<String>,
+    pub title: Option<String>,
+    pub group: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceMode {
+    // A BFS from every node
+    Exact,
+    // `sweeps` double sweeps from random nodes give a lower bound on the diameter, and
+    // BFS from `sources` random nodes estimates the average path length
+    Approximate { sweeps: usize, sources: usize, seed: u64 },
+}
+
+// Eccentricity is the largest distance to any node reachable from this one, so it is
+// finite even when the graph is not connected
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeEccentricity {
+    pub node: usize,
+    pub product_id: u32,
+    pub group: String,
+    pub eccentricity: usize,
+    pub reachable: usize,
+}
+
+// Distances count hops between reachable pairs only. In approximate mode `diameter` is
+// a lower bound and `eccentricities` covers the BFS sources only.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DistanceSummary {
+    pub directed: bool,
+    pub exact: bool,
+    pub node_count: usize,
+    pub sources: usize,
+    pub diameter: usize,
+    pub avg_path_length: Option<f64>,
+    pub reachable_pairs: usize,
+    pub eccentricity_stats: DescriptiveStats,
+    pub eccentricities: Vec<NodeEccentricity>,
+}
+
+// The farthest node from `source` (lowest index on ties) and its distance
+fn farthest_from(adjacency: &[Vec<usize>], source: usize) -> (usize, usize) {
+    let paths = shortest_paths_from(adjacency, source);
+    paths
+        .order
+        .iter()
+        .map(|&node| (paths.distance[node].unwrap_or_default(), node))
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map_or((source, 0), |(distance, node)| (node, distance))
+}
+
+pub fn calculate_distances<N: GroupedNode, E>(
+    graph: &Graph<N, E>,
+    directed: bool,
+    mode: DistanceMode,
+) -> DistanceSummary {
+    let node_count = graph.node_count();
+    let (adjacency, _) = traversal_adjacency(graph, directed);
+
+    let mut diameter = 0;
+    let sources: Vec<usize> = match mode {
+        DistanceMode::Exact => (0..node_count).collect(),
+        DistanceMode::Approximate { sweeps, sources, seed } => {
+            let mut rng = seeded_rng(seed);
+            for _ in 0..sweeps.min(node_count) {
+                let (far, _) = farthest_from(&adjacency, rng.gen_range(0..node_count));
+                diameter = diameter.max(farthest_from(&adjacency, far).1);
+            }
+            let mut sampled = index::sample(&mut rng, node_count, sources.min(node_count)).into_vec();
+            sampled.sort_unstable();
+            sampled
+        }
+    };
+
+    let (mut total_distance, mut reachable_pairs) = (0, 0);
+    let eccentricities: Vec<NodeEccentricity> = sources
+        .iter()
+        .map(|&source| {
+            let paths = shortest_paths_from(&adjacency, source);
+            let distances = paths.order[1..].iter().map(|&node| paths.distance[node].unwrap_or_default());
+            let eccentricity = distances.clone().max().unwrap_or(0);
+            total_distance += distances.sum::<usize>();
+            reachable_pairs += paths.order.len() - 1;
+            let node = NodeIndex::new(source);
+            NodeEccentricity {
+                node: source,
+                product_id: graph[node].product_id(),
+                group: graph[node].group().to_string(),
+                eccentricity,
+                reachable: paths.order.len() - 1,
+            }
+        })
+        .collect();
+    diameter = eccentricities.iter().map(|e| e.eccentricity).fold(diameter, usize::max);
+
+    DistanceSummary {
+        directed,
+        exact: mode == DistanceMode::Exact,
+        node_count,
+        sources: sources.len(),
+        diameter,
+        avg_path_length: (reachable_pairs > 0).then(|| total_distance as f64 / reachable_pairs as f64),
+        reachable_pairs,
+        eccentricity_stats: DescriptiveStats::from_values(
+            eccentricities.iter().map(|e| e.eccentricity as f64).collect(),
+            0,
+        ),
+        eccentricities,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryDistances {
+    pub category: String,
+    pub node_count: usize,
+    pub exact: bool,
+    pub diameter: usize,
+    pub avg_path_length: Option<f64>,
+    pub reachable_pairs: usize,
+}
+
+impl CategoryDistances {
+    pub fn from_summary(category: &str, summary: &DistanceSummary) -> Self {
+        CategoryDistances {
+            category: category.to_string(),
+            node_count: summary.node_count,
+            exact: summary.exact,
+            diameter: summary.diameter,
+            avg_path_length: summary.avg_path_length,
+            reachable_pairs: summary.reachable_pairs,
+        }
+    }
+}
+
+// Percentile interval of a category's in-category ratio over subsamples of `sample_size`
+// products; the cross-category interval is one minus it. It describes the ratio at that
+// sample size, not on the full data. `subsamples` counts the ones the category had edges in.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubsamplingInterval {
+    pub lower: f64,
+    pub upper: f64,
+    pub sample_size: usize,
+    pub subsamples: usize,
+}
+
+// One-sided test of in-category preference against shuffled group labels: `p_value`
+// is the share of shuffles with an in-category ratio at least as high as observed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PermutationTest {
+    pub observed: f64,
+    pub null_mean: f64,
+    pub p_value: f64,
+    pub permutations: usize,
+}
+
+// A `CoPurchaseRatio` row with its interval and test, flat so it can be written as CSV
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoPurchaseSignificance {
+    pub category: String,
+    pub in_category_ratio: f64,
+    pub cross_category_ratio: f64,
+    pub interval_lower: Option<f64>,
+    pub interval_upper: Option<f64>,
+    pub interval_sample_size: Option<usize>,
+    pub subsamples: usize,
+    pub null_in_category_ratio: Option<f64>,
+    pub p_value: Option<f64>,
+}
+
 pub struct AmazonDataAnalysis;
 
 impl AmazonDataAnalysis {
-    pub fn calculate_co_purchase_ratios(
-        global_graph: &Graph<(u32, String), ()>,
+    pub fn calculate_co_purchase_ratios<N: GroupedNode, E>(
+        global_graph: &Graph<N, E>,
     ) -> HashMap<String, (f64, f64)> {
         let mut category_ratios = HashMap::new();
     
@@ -29,13 +1331,13 @@ impl AmazonDataAnalysis {
         for edge in global_graph.edge_indices() {
             // Get source and target nodes for the edge
             if let Some((source_node, target_node)) = global_graph.edge_endpoints(edge) {
-                let source_category = global_graph.node_weight(source_node).map(|(_, cat)| cat);
-                let target_category = global_graph.node_weight(target_node).map(|(_, cat)| cat);
+                let source_category = global_graph.node_weight(source_node).map(|n| n.group());
+                let target_category = global_graph.node_weight(target_node).map(|n| n.group());
     
                 if let (Some(source_cat), Some(target_cat)) = (source_category, target_category) {
                     // Increment cross-category or in-category counters
                     let counts = category_edge_counts
-                        .entry(source_cat.clone())
+                        .entry(source_cat.to_string())
                         .or_insert((0, 0)); 
     
                     if source_cat == target_cat {
@@ -67,7 +1369,793 @@ impl AmazonDataAnalysis {
     
         category_ratios
     }
-    
+
+    // `calculate_co_purchase_ratios` as named rows, sorted by category
+    pub fn co_purchase_ratio_table<N: GroupedNode, E>(global_graph: &Graph<N, E>) -> Vec<CoPurchaseRatio> {
+        let mut table: Vec<CoPurchaseRatio> = Self::calculate_co_purchase_ratios(global_graph)
+            .into_iter()
+            .map(|(category, (in_category_ratio, cross_category_ratio))| CoPurchaseRatio {
+                category,
+                in_category_ratio,
+                cross_category_ratio,
+            })
+            .collect();
+        table.sort_by(|a, b| a.category.cmp(&b.category));
+        table
+    }
+
+    // Average degree centrality of each per-category graph, sorted by category
+    pub fn category_centrality_table<N, E>(category_graphs: &HashMap<String, Graph<N, E>>) -> Vec<CategoryCentrality> {
+        let mut table: Vec<CategoryCentrality> = category_graphs
+            .iter()
+            .map(|(category, graph)| CategoryCentrality {
+                category: category.clone(),
+                node_count: graph.node_count(),
+                edge_count: graph.edge_count(),
+                avg_degree_centrality: calculate_average_degree_centrality(graph),
+            })
+            .collect();
+        table.sort_by(|a, b| a.category.cmp(&b.category));
+        table
+    }
+
+    // Component counts and giant component of each per-category graph, sorted by category
+    pub fn component_table<N, E>(
+        category_graphs: &HashMap<String, Graph<N, E>>,
+        connectivity: Connectivity,
+    ) -> Vec<CategoryComponents> {
+        let mut table: Vec<CategoryComponents> = category_graphs
+            .iter()
+            .map(|(category, graph)| {
+                CategoryComponents::from_analysis(category, &analyze_components(graph, connectivity))
+            })
+            .collect();
+        table.sort_by(|a, b| a.category.cmp(&b.category));
+        table
+    }
+
+    // Draw `subsamples` samples of `sample_size` products without replacement with
+    // `random_sample_seeded` and take percentile intervals of each category's in-category
+    // ratio. These are not rescaled to the full data: they show how much the ratio of a
+    // sample of this size varies. Resampling with replacement would repeat ASINs in one
+    // graph, so the sample must be strictly smaller than the data; a sample of everything
+    // would give zero-width intervals.
+    pub fn subsample_co_purchase_ratios(
+        cleaner: &AmazonDataCleaner,
+        sample_size: usize,
+        subsamples: usize,
+        confidence: f64,
+        seed: u64,
+    ) -> Result<HashMap<String, SubsamplingInterval>, String> {
+        if sample_size == 0 || sample_size >= cleaner.data.len() {
+            return Err(format!(
+                "subsampling needs a sample size between 1 and {}, got {}",
+                cleaner.data.len().saturating_sub(1),
+                sample_size
+            ));
+        }
+        let mut rng = seeded_rng(seed);
+        let mut ratios: HashMap<String, Vec<f64>> = HashMap::new();
+        for _ in 0..subsamples {
+            let sample = AmazonDataCleaner::from_products(cleaner.random_sample_seeded(sample_size, rng.gen()));
+            for (category, (in_category, _)) in Self::calculate_co_purchase_ratios(&sample.create_global_graph()) {
+                ratios.entry(category).or_default().push(in_category);
+            }
+        }
+
+        let tail = (1.0 - confidence) / 2.0;
+        Ok(ratios
+            .into_iter()
+            .map(|(category, mut values)| {
+                values.sort_by(f64::total_cmp);
+                let interval = SubsamplingInterval {
+                    lower: quantile(&values, tail),
+                    upper: quantile(&values, 1.0 - tail),
+                    sample_size,
+                    subsamples: values.len(),
+                };
+                (category, interval)
+            })
+            .collect())
+    }
+
+    pub fn permutation_test_co_purchase<N: GroupedNode, E>(
+        global_graph: &Graph<N, E>,
+        permutations: usize,
+        seed: u64,
+    ) -> HashMap<String, PermutationTest> {
+        let mut group_ids: BTreeMap<&str, usize> = BTreeMap::new();
+        for node in global_graph.node_weights() {
+            let next = group_ids.len();
+            group_ids.entry(node.group()).or_insert(next);
+        }
+        let mut labels: Vec<usize> = global_graph.node_weights().map(|node| group_ids[node.group()]).collect();
+        let edges: Vec<(usize, usize)> = global_graph
+            .edge_references()
+            .map(|edge| (edge.source().index(), edge.target().index()))
+            .collect();
+
+        // In-category ratio of every group whose nodes have out-edges under `labels`
+        let in_category_ratios = |labels: &[usize]| {
+            let mut counts = vec![(0usize, 0usize); group_ids.len()];
+            for &(source, target) in &edges {
+                let count = &mut counts[labels[source]];
+                count.1 += 1;
+                if labels[source] == labels[target] {
+                    count.0 += 1;
+                }
+            }
+            counts
+                .into_iter()
+                .map(|(within, total)| (total > 0).then(|| within as f64 / total as f64))
+                .collect::<Vec<_>>()
+        };
+
+        let observed = in_category_ratios(&labels);
+        // Per group: shuffles where it had out-edges, their ratio sum, and how many were
+        // at least as high as observed
+        let mut null = vec![(0usize, 0.0, 0usize); group_ids.len()];
+        let mut rng = seeded_rng(seed);
+        for _ in 0..permutations {
+            labels.shuffle(&mut rng);
+            for (group, ratio) in in_category_ratios(&labels).into_iter().enumerate() {
+                if let (Some(ratio), Some(observed)) = (ratio, observed[group]) {
+                    null[group].0 += 1;
+                    null[group].1 += ratio;
+                    if ratio >= observed - 1e-12 {
+                        null[group].2 += 1;
+                    }
+                }
+            }
+        }
+
+        group_ids
+            .into_iter()
+            .filter_map(|(group, id)| {
+                let observed = observed[id]?;
+                let (count, sum, extreme) = null[id];
+                let test = PermutationTest {
+                    observed,
+                    null_mean: if count == 0 { observed } else { sum / count as f64 },
+                    p_value: (extreme + 1) as f64 / (count + 1) as f64,
+                    permutations: count,
+                };
+                Some((group.to_string(), test))
+            })
+            .collect()
+    }
+
+    // Join the ratio table with subsampling intervals and permutation tests, either of
+    // which may be empty
+    pub fn co_purchase_significance_table(
+        ratios: &[CoPurchaseRatio],
+        intervals: &HashMap<String, SubsamplingInterval>,
+        tests: &HashMap<String, PermutationTest>,
+    ) -> Vec<CoPurchaseSignificance> {
+        ratios
+            .iter()
+            .map(|row| {
+                let interval = intervals.get(&row.category);
+                let test = tests.get(&row.category);
+                CoPurchaseSignificance {
+                    category: row.category.clone(),
+                    in_category_ratio: row.in_category_ratio,
+                    cross_category_ratio: row.cross_category_ratio,
+                    interval_lower: interval.map(|i| i.lower),
+                    interval_upper: interval.map(|i| i.upper),
+                    interval_sample_size: interval.map(|i| i.sample_size),
+                    subsamples: interval.map_or(0, |i| i.subsamples),
+                    null_in_category_ratio: test.map(|t| t.null_mean),
+                    p_value: test.map(|t| t.p_value),
+                }
+            })
+            .collect()
+    }
+
+    // Degree, salesrank and rating assortativity, with the average nearest-neighbour
+    // degree. Node salesranks never include the ones `clean_data` filled in.
+    pub fn assortativity(graph: &ProductGraph) -> Assortativity {
+        let salesranks: Vec<Option<f64>> = graph.node_weights().map(|node| node.salesrank.map(f64::from)).collect();
+        // SNAP writes `avg rating: 0` for products without reviews
+        let ratings: Vec<Option<f64>> = graph
+            .node_weights()
+            .map(|node| node.avg_rating.filter(|_| node.review_count != Some(0)).map(f64::from))
+            .collect();
+
+        let mut coefficients = calculate_degree_assortativity(graph);
+        coefficients.push(numeric_assortativity(graph, "salesrank", &salesranks));
+        coefficients.push(numeric_assortativity(graph, "avg_rating", &ratings));
+        Assortativity {
+            coefficients,
+            neighbor_degree: calculate_average_neighbor_degree(graph),
+        }
+    }
+
+    // One shortest path between two ASINs of a graph built from `cleaner`'s data, with
+    // product titles; None when either ASIN is not in the graph or there is no path
+    pub fn shortest_path_between(
+        cleaner: &AmazonDataCleaner,
+        graph: &ProductGraph,
+        from: &str,
+        to: &str,
+        directed: bool,
+    ) -> Option<Vec<PathStep>> {
+        let source = *seed_nodes_by_asin(graph, &[from.to_string()]).first()?;
+        let target = *seed_nodes_by_asin(graph, &[to.to_string()]).first()?;
+        let path = shortest_path(graph, source, target, directed)?;
+        Some(
+            path.into_iter()
+                .enumerate()
+                .map(|(hop, node)| PathStep {
+                    hop,
+                    node: node.index(),
+                    asin: graph[node].asin.clone(),
+                    title: cleaner.node_title(&graph[node]),
+                    group: graph[node].group.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    // Diameter and average path length of each per-category graph, sorted by category
+    pub fn distance_table<N: GroupedNode, E>(
+        category_graphs: &HashMap<String, Graph<N, E>>,
+        directed: bool,
+        mode: DistanceMode,
+    ) -> Vec<CategoryDistances> {
+        let mut table: Vec<CategoryDistances> = category_graphs
+            .iter()
+            .map(|(category, graph)| {
+                CategoryDistances::from_summary(category, &calculate_distances(graph, directed, mode))
+            })
+            .collect();
+        table.sort_by(|a, b| a.category.cmp(&b.category));
+        table
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_processing::{GroupBy, Product, EXTERNAL_GROUP, EXTERNAL_PRODUCT_ID};
+    use crate::product_graph::{CoPurchaseEdge, ProductNode};
+    use crate::test_fixtures::{asin, chain_products, group_graph, product};
+
+    #[test]
+    fn test_calculate_average_degree_centrality() {
+        let mut graph = Graph::<(u32, String), ()>::new();
+
+        let node_a = graph.add_node((1, "Books".to_string())); // Product 1 in Books
+        let node_b = graph.add_node((2, "Books".to_string())); // Product 2 in Books
+        let node_c = graph.add_node((3, "Music".to_string())); // Product 3 in Music
+        let node_d = graph.add_node((4, "Books".to_string())); // Product 4 in Books
+
+
+        graph.add_edge(node_a, node_b, ()); // Product 1 -> Product 2
+        graph.add_edge(node_b, node_a, ()); // Product 2 -> Product 1
+        graph.add_edge(node_a, node_c, ()); // Product 1 -> Product 3
+        graph.add_edge(node_c, node_a, ()); // Product 3 -> Product 1
+        graph.add_edge(node_b, node_d, ()); // Product 2 -> Product 4
+        graph.add_edge(node_d, node_b, ()); // Product 4 -> Product 2
+
+
+        let avg_degree_centrality = calculate_average_degree_centrality(&graph);
+
+        let expected_avg_degree_centrality = 1.5;
+
+        assert!(
+            (avg_degree_centrality - expected_avg_degree_centrality).abs() < f64::EPSILON,
+            "Expected: {:.2}, Got: {:.2}",
+            expected_avg_degree_centrality,
+            avg_degree_centrality
+        );
+    }
+
     
+    #[test]
+    fn test_calculate_co_purchase_ratios() {
+        let mut global_graph = Graph::<(u32, String), ()>::new();
+
+        let book_node_1 = global_graph.add_node((1, "Book".to_string()));
+        let book_node_2 = global_graph.add_node((2, "Book".to_string()));
+        let music_node_1 = global_graph.add_node((3, "Music".to_string()));
+        let dvd_node_1 = global_graph.add_node((4, "DVD".to_string()));
+
+        global_graph.add_edge(book_node_1, book_node_2, ()); // Book -> Book
+        global_graph.add_edge(book_node_1, music_node_1, ()); // Book -> Music
+        global_graph.add_edge(dvd_node_1, book_node_1, ());  // DVD -> Book
+        global_graph.add_edge(music_node_1, music_node_1, ()); // Music -> Music (self-loop)
+
+        let co_purchase_ratios = AmazonDataAnalysis::calculate_co_purchase_ratios(&global_graph);
+
+        println!("Debug - Available Categories in Ratios: {:?}", co_purchase_ratios.keys());
+
+        assert!(co_purchase_ratios.contains_key("Book"), "Book category not found in ratios");
+        assert!(co_purchase_ratios.contains_key("Music"), "Music category not found in ratios");
+        assert!(co_purchase_ratios.contains_key("DVD"), "DVD category not found in ratios");
+
+
+        assert_eq!(
+            co_purchase_ratios.get("Book").unwrap(),
+            &(0.5, 0.5),
+            "Book category ratios mismatch"
+        );
+        assert_eq!(
+            co_purchase_ratios.get("Music").unwrap(),
+            &(1.0, 0.0),
+            "Music category ratios mismatch"
+        );
+        assert_eq!(
+            co_purchase_ratios.get("DVD").unwrap(),
+            &(0.0, 1.0),
+            "DVD category ratios mismatch"
+        );
+    }
+
+    #[test]
+    fn test_measures_accept_typed_graphs() {
+        let mut typed = ProductGraph::new();
+        let book = typed.add_node(ProductNode::external("B1", EXTERNAL_PRODUCT_ID));
+        let mut music = ProductNode::external("M1", EXTERNAL_PRODUCT_ID - 1);
+        music.group = "Music".to_string();
+        let music = typed.add_node(music);
+        typed.add_edge(book, music, CoPurchaseEdge::forward(0));
+        let ratios = AmazonDataAnalysis::calculate_co_purchase_ratios(&typed);
+        assert_eq!(ratios[EXTERNAL_GROUP], (0.0, 1.0));
+        assert!((calculate_average_degree_centrality(&typed) - 0.5).abs() < f64::EPSILON);
+    }
+
+    // 0 -> 1, 0 -> 2, 1 -> 2, 3 -> 2
+    fn star_graph() -> Graph<(u32, String), ()> {
+        group_graph(&["Book"; 4], &[(0, 1), (0, 2), (1, 2), (3, 2)])
+    }
+
+    #[test]
+    fn test_degree_centrality_counts() {
+        let raw = calculate_degree_centrality(&star_graph(), false, 2);
+        let degrees: Vec<(usize, usize)> = raw.nodes.iter().map(|n| (n.in_degree, n.out_degree)).collect();
+        assert_eq!(degrees, vec![(0, 2), (1, 1), (3, 0), (0, 1)]);
+        assert_eq!(raw.out_degree_stats.mean, Some(1.0));
+        assert_eq!(raw.in_degree_stats.max, Some(3.0));
+        assert_eq!(raw.top_in[0].product_id, 2);
+        assert_eq!(raw.top_out.iter().map(|n| n.product_id).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(raw.top_total.len(), 2);
+        let in_zero = raw.distribution.iter().find(|f| f.measure == "in" && f.degree == 0).unwrap();
+        assert_eq!(in_zero.count, 2);
+    }
+
+    #[test]
+    fn test_normalized_degree_centrality() {
+        let normalized = calculate_degree_centrality(&star_graph(), true, 2);
+        assert!((normalized.nodes[2].in_centrality - 1.0).abs() < 1e-12);
+        assert!((normalized.nodes[0].total_centrality - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(normalized.nodes[0].total_degree, 2);
+    }
+
+    // Products 0, 1 and 2 all list 3 as similar; 3 lists nothing. Only 0 is Music.
+    fn funnel_cleaner() -> AmazonDataCleaner {
+        let products: Vec<Product> = (0..4)
+            .map(|id| Product {
+                title: Some(format!("Title {}", id)),
+                group: Some(if id == 0 { "Music" } else { "Book" }.to_string()),
+                similar: if id < 3 { vec![asin(3)] } else { vec![] },
+                ..product(id)
+            })
+            .collect();
+        AmazonDataCleaner::from_products(products)
+    }
+
+    #[test]
+    fn test_pagerank_ranks_the_shared_target_first() {
+        let cleaner = funnel_cleaner();
+        let graph = cleaner.create_global_graph();
+        let result = calculate_pagerank(&graph, &PageRankOptions::default());
+        assert!(result.convergence.converged);
+        assert!((result.scores.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((result.scores[0] - result.scores[2]).abs() < 1e-12);
+        let top = cleaner.rank_products(&graph, &result.top_k(2));
+        assert_eq!(top[0].title.as_deref(), Some("Title 3"));
+        assert_eq!(top[0].rank, 1);
+        assert_eq!(top.len(), 2);
+    }
+
+    #[test]
+    fn test_personalized_pagerank_only_jumps_to_seeds() {
+        let graph = funnel_cleaner().create_global_graph();
+        let options = PageRankOptions::default();
+        let seeds = seed_nodes_by_asin(&graph, &[asin(0).to_lowercase(), "missing".to_string()]);
+        assert_eq!(seeds, seed_nodes_by_group(&graph, "Music"));
+
+        // Jumps only land on product 0, so 1 and 2 are never reached
+        let personalized = calculate_personalized_pagerank(&graph, &options, &seeds);
+        assert!(personalized.scores[1].abs() < 1e-12);
+        assert!(personalized.scores[0] > calculate_pagerank(&graph, &options).scores[0]);
+        assert!((personalized.scores[0] + personalized.scores[3] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pagerank_reports_the_iteration_cap() {
+        let graph = funnel_cleaner().create_global_graph();
+        let capped = calculate_pagerank(&graph, &PageRankOptions { max_iterations: 1, ..PageRankOptions::default() });
+        assert_eq!(capped.convergence.iterations, 1);
+        assert!(!capped.convergence.converged);
+    }
+
+    #[test]
+    fn test_exact_path_centrality() {
+        // 0 -> 1 -> 2 -> 3, with a duplicate 0 -> 1 edge that must not add a path
+        let graph = group_graph(&["Book"; 4], &[(0, 1), (1, 2), (2, 3), (0, 1)]);
+        let exact = calculate_path_centrality(&graph, PathCentralityMode::Exact, 2);
+        assert!(exact.exact);
+        assert_eq!(exact.error_bound, None);
+        let betweenness: Vec<f64> = exact.nodes.iter().map(|n| n.betweenness).collect();
+        assert_eq!(betweenness, vec![0.0, 1.0 / 3.0, 1.0 / 3.0, 0.0]);
+        assert!((exact.nodes[0].closeness - 0.5).abs() < 1e-12);
+        assert!((exact.nodes[0].harmonic - 11.0 / 18.0).abs() < 1e-12);
+        assert_eq!(exact.nodes[3].closeness, 0.0);
+        assert_eq!(exact.top_harmonic[0].product_id, 0);
+    }
+
+    #[test]
+    fn test_approximate_path_centrality_stays_within_its_bound() {
+        let global = AmazonDataCleaner::from_products(chain_products(60)).create_global_graph();
+        assert!(pivot_count(60, 0.3, 0.1) < 60);
+        let exact = calculate_path_centrality(&global, PathCentralityMode::Exact, 5);
+        let mode = PathCentralityMode::Approximate { epsilon: 0.3, delta: 0.1, seed: 3 };
+        let approximate = calculate_path_centrality(&global, mode, 5);
+        assert!(!approximate.exact);
+        assert_eq!(approximate.pivots, pivot_count(60, 0.3, 0.1));
+        let bound = approximate.error_bound.unwrap();
+        for (a, e) in approximate.nodes.iter().zip(&exact.nodes) {
+            assert!((a.betweenness - e.betweenness).abs() <= bound);
+            assert!((a.harmonic - e.harmonic).abs() <= bound);
+        }
+        assert_eq!(approximate, calculate_path_centrality(&global, mode, 5));
+    }
+
+    #[test]
+    fn test_eigenvector_centrality_of_a_fed_cycle() {
+        // A 0 -> 1 -> 2 -> 0 cycle fed by 3, which nothing lists
+        let graph = group_graph(&["Book"; 4], &[(0, 1), (1, 2), (2, 0), (3, 0)]);
+        let eigenvector = calculate_eigenvector_centrality(&graph, &PowerIterationOptions::default());
+        assert!(eigenvector.convergence.converged);
+        for score in &eigenvector.scores[..3] {
+            assert!((score - 1.0 / 3.0).abs() < 1e-9);
+        }
+        assert!(eigenvector.scores[3] < 1e-9);
+    }
+
+    // 0 lists 2 and 3, 1 lists 2: hubs and authorities split in the golden ratio
+    fn hits_cleaner() -> AmazonDataCleaner {
+        let products: Vec<Product> = (0..4)
+            .map(|id| Product {
+                title: Some(format!("Title {}", id)),
+                group: Some("Book".to_string()),
+                salesrank: Some(100 + id),
+                similar: match id {
+                    0 => vec![asin(2), asin(3)],
+                    1 => vec![asin(2)],
+                    _ => vec![],
+                },
+                ..product(id)
+            })
+            .collect();
+        AmazonDataCleaner::from_products(products)
+    }
+
+    #[test]
+    fn test_hits_hubs_and_authorities() {
+        let hits = calculate_hits(&hits_cleaner().create_global_graph(), &PowerIterationOptions::default());
+        assert!(hits.convergence.converged);
+        let golden = (5f64.sqrt() - 1.0) / 2.0;
+        assert!((hits.hubs[0] - golden).abs() < 1e-9);
+        assert!((hits.authorities[2] - golden).abs() < 1e-9);
+        assert_eq!(hits.hubs[2], 0.0);
+        assert_eq!(hits.authorities[0], 0.0);
+    }
+
+    #[test]
+    fn test_hits_products_join_scores_with_product_fields() {
+        let cleaner = hits_cleaner();
+        let graph = cleaner.create_global_graph();
+        let hits = calculate_hits(&graph, &PowerIterationOptions::default());
+        let joined = cleaner.hits_products(&graph, &hits.hubs, &hits.authorities);
+        assert_eq!(joined[2].title.as_deref(), Some("Title 2"));
+        assert_eq!(joined[2].salesrank, Some(102));
+        assert_eq!(joined[2].authority, hits.authorities[2]);
+    }
+
+    // Cycle 0 -> 1 -> 2 -> 0 with a tail 2 -> 3, a pair 4 -> 5 and an isolated 6
+    fn component_graph() -> Graph<(u32, String), ()> {
+        group_graph(&["Book"; 7], &[(0, 1), (1, 2), (2, 0), (2, 3), (4, 5)])
+    }
+
+    #[test]
+    fn test_weak_components() {
+        let weak = analyze_components(&component_graph(), Connectivity::Weak);
+        assert_eq!(weak.component_count, 3);
+        assert_eq!(weak.giant_size, 4);
+        assert!((weak.giant_share - 4.0 / 7.0).abs() < 1e-12);
+        assert_eq!(weak.membership, vec![0, 0, 0, 0, 1, 1, 2]);
+        let sizes: Vec<(usize, usize)> = weak.size_distribution.iter().map(|s| (s.size, s.count)).collect();
+        assert_eq!(sizes, vec![(1, 1), (2, 1), (4, 1)]);
+    }
+
+    #[test]
+    fn test_strong_components() {
+        let strong = analyze_components(&component_graph(), Connectivity::Strong);
+        assert_eq!(strong.component_count, 5);
+        assert_eq!(strong.giant_component(), &(0..3).map(NodeIndex::new).collect::<Vec<_>>()[..]);
+        assert_eq!(strong.size_stats.max, Some(3.0));
+    }
+
+    #[test]
+    fn test_extract_giant_component() {
+        let giant = extract_giant_component(&component_graph(), Connectivity::Weak);
+        assert_eq!(giant.node_count(), 4);
+        assert_eq!(giant.edge_count(), 4);
+        let ids: Vec<u32> = giant.node_weights().map(|n| n.0).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+    }
+
+    // Triangle 0-1-2 (one edge each way round, plus 1 -> 0 doubling an edge) and a tail 2 -> 3
+    fn triangle_graph() -> Graph<(u32, String), ()> {
+        group_graph(&["Book", "Book", "Music", "Music"], &[(0, 1), (1, 0), (1, 2), (2, 0), (2, 3)])
+    }
+
+    #[test]
+    fn test_triangles_and_clustering() {
+        let clustering = calculate_clustering(&triangle_graph());
+        assert_eq!(clustering.triangle_count, 1);
+        let local: Vec<(usize, f64)> = clustering.nodes.iter().map(|n| (n.triangles, n.clustering)).collect();
+        assert_eq!(local, vec![(1, 1.0), (1, 1.0), (1, 1.0 / 3.0), (0, 0.0)]);
+        assert!((clustering.avg_clustering - (7.0 / 3.0) / 4.0).abs() < 1e-12);
+        // 3 closed triples out of 1 + 1 + 3 connected ones
+        assert!((clustering.transitivity - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_clustering_by_group() {
+        let clustering = calculate_clustering(&triangle_graph());
+        let book = &clustering.categories[0];
+        assert_eq!((book.category.as_str(), book.node_count, book.triangle_corners), ("Book", 2, 2));
+        assert_eq!(book.transitivity, 1.0);
+        let music = &clustering.categories[1];
+        assert!((music.avg_clustering - 1.0 / 6.0).abs() < 1e-12);
+        assert!((music.transitivity - 1.0 / 3.0).abs() < 1e-12);
+        // Every row counts triangle corners, so the global row has three per triangle
+        let overall = clustering.overall("(global)");
+        assert_eq!(overall.triangle_corners, 3 * clustering.triangle_count);
+        assert_eq!(overall.avg_clustering, clustering.avg_clustering);
+        assert_eq!(overall.transitivity, clustering.transitivity);
+    }
+
+    // Book 0, 1 and Music 2, 3: Book -> Book once, Book -> Music twice, Music -> Music twice
+    fn mixing_graph() -> Graph<(u32, String), ()> {
+        group_graph(&["Book", "Book", "Music", "Music"], &[(0, 1), (1, 2), (0, 2), (2, 3), (3, 2)])
+    }
+
+    #[test]
+    fn test_group_mixing_matrix() {
+        let mixing = calculate_group_mixing_matrix(&mixing_graph(), true);
+        assert_eq!(mixing.labels, vec!["Book", "Music"]);
+        assert_eq!(mixing.edge_count, 5);
+        let book_music = mixing.cell("Book", "Music").unwrap();
+        assert_eq!(book_music.count, 2.0);
+        assert!((book_music.probability - 2.0 / 3.0).abs() < 1e-12);
+        assert!((book_music.expected - 2.4).abs() < 1e-12);
+        // Never observed, but expected under the degree-preserving null
+        let music_book = mixing.cell("Music", "Book").unwrap();
+        assert_eq!(music_book.count, 0.0);
+        assert!((music_book.expected - 0.4).abs() < 1e-12);
+        assert!((mixing.assortativity.unwrap() - 0.16 / 0.56).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mixing_matrix_without_unobserved_pairs() {
+        let mixing = calculate_group_mixing_matrix(&mixing_graph(), true);
+        let observed = calculate_group_mixing_matrix(&mixing_graph(), false);
+        assert_eq!(observed.cells.len(), 3);
+        assert_eq!(observed.cell("Music", "Book"), None);
+        assert_eq!(observed.cell("Book", "Music"), mixing.cell("Book", "Music"));
+        assert_eq!(observed.assortativity, mixing.assortativity);
+    }
+
+    #[test]
+    fn test_mixing_matrix_splits_edges_between_categories() {
+        // At category depth 0, product 0 is in two roots and splits its edge between them
+        let products = vec![
+            Product {
+                category_list: vec!["|Books[1]|Fiction[2]".to_string(), "|Music[5]|Pop[6]".to_string()],
+                similar: vec![asin(1)],
+                ..product(0)
+            },
+            Product {
+                category_list: vec!["|Books[1]|History[3]".to_string()],
+                similar: vec![asin(2)],
+                ..product(1)
+            },
+            product(2),
+        ];
+        let cleaner = AmazonDataCleaner::from_products(products);
+        let global = cleaner.create_global_graph();
+        let labels = cleaner.node_labels(&global, GroupBy::CategoryDepth(0));
+        let mixing = calculate_mixing_matrix(&global, &labels, false);
+        assert_eq!((mixing.edge_count, mixing.unlabelled_edges), (1, 1));
+        assert_eq!(mixing.cell("Music[5]", "Books[1]").unwrap().count, 0.5);
+        assert_eq!(mixing.assortativity, Some(0.0));
+    }
+
+    // Book and Music rings of five that only list their own group
+    fn segregated_graph() -> Graph<(u32, String), ()> {
+        let groups: Vec<&str> = (0..10).map(|id| if id < 5 { "Book" } else { "Music" }).collect();
+        let edges: Vec<(usize, usize)> = (0..10).map(|id| (id, id / 5 * 5 + (id + 1) % 5)).collect();
+        group_graph(&groups, &edges)
+    }
+
+    #[test]
+    fn test_permutation_test_detects_in_group_preference() {
+        let graph = segregated_graph();
+        let tests = AmazonDataAnalysis::permutation_test_co_purchase(&graph, 200, 1);
+        let book = &tests["Book"];
+        assert_eq!((book.observed, book.permutations), (1.0, 200));
+        assert!(book.null_mean < 0.8);
+        assert!(book.p_value < 0.05);
+        assert_eq!(tests, AmazonDataAnalysis::permutation_test_co_purchase(&graph, 200, 1));
+    }
+
+    #[test]
+    fn test_subsampling_intervals() {
+        let cleaner = AmazonDataCleaner::from_products(chain_products(200));
+        let intervals = AmazonDataAnalysis::subsample_co_purchase_ratios(&cleaner, 100, 20, 0.9, 5).unwrap();
+        let music = &intervals["Music"];
+        assert_eq!((music.sample_size, music.subsamples), (100, 20));
+        assert!(music.lower <= music.upper);
+        assert!(music.lower < 0.5 && music.upper > 0.5);
+    }
+
+    #[test]
+    fn test_subsamples_must_be_smaller_than_the_data() {
+        // Subsamples of everything would all be the same graph
+        let cleaner = AmazonDataCleaner::from_products(chain_products(200));
+        for sample_size in [0, 200, 300] {
+            assert!(AmazonDataAnalysis::subsample_co_purchase_ratios(&cleaner, sample_size, 20, 0.9, 5).is_err());
+        }
+    }
+
+    #[test]
+    fn test_significance_table_joins_intervals_and_tests() {
+        let graph = segregated_graph();
+        let tests = AmazonDataAnalysis::permutation_test_co_purchase(&graph, 200, 1);
+        let cleaner = AmazonDataCleaner::from_products(chain_products(200));
+        let intervals = AmazonDataAnalysis::subsample_co_purchase_ratios(&cleaner, 100, 20, 0.9, 5).unwrap();
+
+        let ratios = AmazonDataAnalysis::co_purchase_ratio_table(&graph);
+        let table = AmazonDataAnalysis::co_purchase_significance_table(&ratios, &intervals, &tests);
+        assert_eq!(table[0].category, "Book");
+        assert_eq!(table[0].p_value, Some(tests["Book"].p_value));
+        assert_eq!(table[0].interval_lower, Some(intervals["Book"].lower));
+        assert_eq!((table[1].interval_sample_size, table[1].subsamples), (Some(100), 20));
+    }
+
+    // 0 -> 1, 0 -> 2, 1 -> 2
+    fn transitive_graph() -> Graph<(u32, String), ()> {
+        group_graph(&["Book"; 3], &[(0, 1), (0, 2), (1, 2)])
+    }
+
+    #[test]
+    fn test_degree_assortativity() {
+        let coefficients: Vec<(String, Option<f64>)> = calculate_degree_assortativity(&transitive_graph())
+            .into_iter()
+            .map(|c| (c.measure, c.coefficient.map(|r| (r * 1e9).round() / 1e9)))
+            .collect();
+        assert_eq!(
+            coefficients,
+            vec![
+                ("degree out-in".to_string(), Some(-0.5)),
+                ("degree in-in".to_string(), Some(0.5)),
+                ("degree out-out".to_string(), Some(0.5)),
+                ("degree in-out".to_string(), Some(-0.5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_average_neighbor_degree() {
+        let out_in: Vec<(usize, usize, f64)> = calculate_average_neighbor_degree(&transitive_graph())
+            .into_iter()
+            .filter(|row| row.variant == "out-in")
+            .map(|row| (row.degree, row.node_count, row.avg_neighbor_degree))
+            .collect();
+        assert_eq!(out_in, vec![(1, 1, 2.0), (2, 1, 1.5)]);
+    }
+
+    #[test]
+    fn test_attribute_assortativity_skips_imputed_ranks_and_unreviewed_ratings() {
+        // The transitive graph from products; product 2's salesrank was filled in by
+        // cleaning, and product 3, listed by product 2, has no reviews behind its rating of 0
+        let mut products: Vec<Product> = (0..3)
+            .map(|id| Product {
+                group: Some("Book".to_string()),
+                salesrank: Some(10 * (id + 1)),
+                salesrank_imputed: id == 2,
+                avg_rating: Some(1.0 + 2.0 * id as f32),
+                similar: (id + 1..3).map(asin).collect(),
+                ..product(id)
+            })
+            .collect();
+        products[2].similar.push(asin(3));
+        products.push(Product {
+            group: Some("Book".to_string()),
+            avg_rating: Some(0.0),
+            total_reviews: Some(0),
+            ..product(3)
+        });
+        let cleaner = AmazonDataCleaner::from_products(products);
+        let assortativity = AmazonDataAnalysis::assortativity(&cleaner.create_global_graph());
+        let salesrank = assortativity.coefficients.iter().find(|c| c.measure == "salesrank").unwrap();
+        assert_eq!((salesrank.pairs, salesrank.coefficient), (1, None));
+        let rating = assortativity.coefficients.iter().find(|c| c.measure == "avg_rating").unwrap();
+        assert_eq!(rating.pairs, 3);
+        assert!((rating.coefficient.unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(assortativity.neighbor_degree.len(), 10);
+    }
+
+    // A directed cycle 0 -> 1 -> 2 -> 3 -> 0 and an unconnected product 4
+    fn cycle_cleaner() -> AmazonDataCleaner {
+        let products: Vec<Product> = (0..5)
+            .map(|id| Product {
+                title: Some(format!("Title {}", id)),
+                group: Some("Book".to_string()),
+                similar: if id < 4 { vec![asin((id + 1) % 4)] } else { vec![] },
+                ..product(id)
+            })
+            .collect();
+        AmazonDataCleaner::from_products(products)
+    }
+
+    #[test]
+    fn test_shortest_path_between_asins() {
+        let cleaner = cycle_cleaner();
+        let graph = cleaner.create_global_graph();
+        let path = AmazonDataAnalysis::shortest_path_between(&cleaner, &graph, &asin(0), &asin(3), true).unwrap();
+        let titles: Vec<&str> = path.iter().map(|step| step.title.as_deref().unwrap()).collect();
+        assert_eq!(titles, vec!["Title 0", "Title 1", "Title 2", "Title 3"]);
+        let lowercase = asin(0).to_lowercase();
+        let undirected = AmazonDataAnalysis::shortest_path_between(&cleaner, &graph, &lowercase, &asin(3), false);
+        assert_eq!(undirected.unwrap().iter().map(|step| step.node).collect::<Vec<_>>(), vec![0, 3]);
+        assert!(AmazonDataAnalysis::shortest_path_between(&cleaner, &graph, &asin(0), &asin(4), true).is_none());
+        assert!(AmazonDataAnalysis::shortest_path_between(&cleaner, &graph, &asin(0), "missing", true).is_none());
+    }
+
+    #[test]
+    fn test_exact_distances() {
+        let graph = cycle_cleaner().create_global_graph();
+        let directed = calculate_distances(&graph, true, DistanceMode::Exact);
+        assert_eq!((directed.diameter, directed.reachable_pairs), (3, 12));
+        assert_eq!(directed.avg_path_length, Some(2.0));
+        let eccentricities: Vec<usize> = directed.eccentricities.iter().map(|e| e.eccentricity).collect();
+        assert_eq!(eccentricities, vec![3, 3, 3, 3, 0]);
+        let undirected = calculate_distances(&graph, false, DistanceMode::Exact);
+        assert_eq!(undirected.diameter, 2);
+        assert!((undirected.avg_path_length.unwrap() - 4.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_approximate_distances_are_reproducible_lower_bounds() {
+        let graph = cycle_cleaner().create_global_graph();
+        let mode = DistanceMode::Approximate { sweeps: 3, sources: 2, seed: 9 };
+        let approximate = calculate_distances(&graph, true, mode);
+        assert!(!approximate.exact);
+        assert_eq!(approximate.sources, 2);
+        assert!(approximate.diameter <= 3);
+        assert_eq!(approximate, calculate_distances(&graph, true, mode));
+    }
+
+    #[test]
+    fn test_distance_table_covers_category_graphs() {
+        let graphs = cycle_cleaner().create_category_graphs(&["Book".to_string()]);
+        let table = AmazonDataAnalysis::distance_table(&graphs, true, DistanceMode::Exact);
+        assert_eq!((table[0].category.as_str(), table[0].diameter), ("Book", 3));
+    }
+}
@@ -1,20 +1,35 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader}; 
+use std::io::{self, BufRead, BufReader, Lines};
+use std::fmt;
+use std::str::FromStr;
 use regex::Regex;
 use serde::{Serialize, Deserialize};
 use std::error::Error;
-use petgraph::Graph;
+use petgraph::graph::NodeIndex;
+use std::cell::OnceCell;
 use std::collections::HashMap;
-use rand::seq::SliceRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
+pub use crate::asin_index::AsinIndex;
+pub use crate::category::{Category, CategoryTree};
+use crate::statistics::DescriptiveStats;
+use crate::snapshot::{read_snapshot, write_snapshot, SourceFingerprint};
+use crate::product_graph::{mark_reciprocated, CoPurchaseEdge, ProductGraph, ProductNode};
+use crate::category::group_products_at_depth;
+use crate::sampling::{
+    random_seed, seeded_rng, stratified_sample, Allocation, SamplingStrategy, StratifiedSample,
+};
 
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Product {
     pub id: u32,
     pub asin: Option<String>,
     pub title: Option<String>,
     pub group: Option<String>,
     pub salesrank: Option<u32>,
+    // Set by `clean_data` when `salesrank` is a filled-in placeholder
+    #[serde(default)]
+    pub salesrank_imputed: bool,
     pub similar: Vec<String>,
     pub categories: Option<u32>,
     pub category_list: Vec<String>,
@@ -22,6 +37,229 @@ pub struct Product {
     pub downloaded_reviews: Option<u32>,
     pub avg_rating: Option<f32>,
     pub reviews: Vec<Review>,
+    #[serde(default)]
+    pub status: ProductStatus,
+}
+
+// SNAP lists removed products as `discontinued product` records with only an ASIN
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProductStatus {
+    #[default]
+    Active,
+    Discontinued,
+}
+
+// What to do with similar ASINs that are not in the loaded data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DanglingPolicy {
+    // Drop the edge, but count it in the `GraphBuildReport`
+    #[default]
+    Skip,
+    // Add one placeholder node per unknown ASIN, in the `EXTERNAL_GROUP` group.
+    // Per-category graphs ignore this: an unknown ASIN has no category, so its
+    // placeholder would always fall outside the graph.
+    External,
+}
+
+pub const EXTERNAL_GROUP: &str = "External";
+// Placeholder ids count down from here, far above any SNAP `Id`
+pub const EXTERNAL_PRODUCT_ID: u32 = u32::MAX;
+
+// One row of `summarize_categories`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategorySummary {
+    pub category: String,
+    pub product_count: usize,
+    // Mean of the real salesranks; imputed ones are left out
+    pub avg_salesrank: Option<f64>,
+    pub avg_rating: Option<f64>,
+    // Sum of the review totals (or downloaded reviews where the total is missing)
+    pub review_volume: u64,
+    // Mean out-degree of the category's products in the global graph
+    pub avg_degree: f64,
+}
+
+// The level categories are summarized at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupBy {
+    #[default]
+    Group,
+    // Categories at this depth of `category_list` (0 is the root, e.g. `Books`)
+    CategoryDepth(usize),
+}
+
+impl GroupBy {
+    // The categories a product belongs to at this level, as `Name[id]` labels for
+    // category depths
+    pub fn labels_of(&self, product: &Product) -> Vec<String> {
+        match self {
+            GroupBy::Group => product.group.iter().cloned().collect(),
+            GroupBy::CategoryDepth(depth) => {
+                let mut labels: Vec<String> = product
+                    .category_paths()
+                    .iter()
+                    .filter_map(|path| path.get(*depth).map(|c| c.label()))
+                    .collect();
+                labels.sort();
+                labels.dedup();
+                labels
+            }
+        }
+    }
+
+    // The one stratum a product is sampled from: the first of `labels_of`, or
+    // `Unknown` when it has none
+    pub fn stratum_of(&self, product: &Product) -> String {
+        self.labels_of(product)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RankBy {
+    // Most products first
+    #[default]
+    ProductCount,
+    // Best-selling (lowest mean salesrank) first; categories without ranks last
+    MeanSalesrank,
+    // Highest mean review rating first; categories without reviews last
+    MeanRating,
+    // Most reviews first
+    ReviewVolume,
+    // Highest mean out-degree first
+    GraphDegree,
+}
+
+impl RankBy {
+    // Ties are broken by category name so the order is stable
+    pub fn sort(&self, summaries: &mut [CategorySummary]) {
+        summaries.sort_by(|a, b| {
+            let order = match self {
+                RankBy::ProductCount => b.product_count.cmp(&a.product_count),
+                RankBy::MeanSalesrank => match (a.avg_salesrank, b.avg_salesrank) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (a, b) => b.is_some().cmp(&a.is_some()),
+                },
+                RankBy::MeanRating => match (a.avg_rating, b.avg_rating) {
+                    (Some(a), Some(b)) => b.total_cmp(&a),
+                    (a, b) => b.is_some().cmp(&a.is_some()),
+                },
+                RankBy::ReviewVolume => b.review_volume.cmp(&a.review_volume),
+                RankBy::GraphDegree => b.avg_degree.total_cmp(&a.avg_degree),
+            };
+            order.then_with(|| a.category.cmp(&b.category))
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryOptions {
+    pub group_by: GroupBy,
+    pub rank_by: RankBy,
+    // `None` keeps every category
+    pub top_n: Option<usize>,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        SummaryOptions {
+            group_by: GroupBy::Group,
+            rank_by: RankBy::ProductCount,
+            top_n: Some(3),
+        }
+    }
+}
+
+// Per-category distributions of `describe_categories`. Helpful votes are summed over
+// each product's downloaded reviews.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryStatistics {
+    pub category: String,
+    pub product_count: usize,
+    pub salesrank: DescriptiveStats,
+    pub rating: DescriptiveStats,
+    pub review_count: DescriptiveStats,
+    pub helpful_votes: DescriptiveStats,
+}
+
+// One (category, variable) row of `CategoryStatistics`, flat so it can be written as CSV
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatisticsRow<'a> {
+    pub category: &'a str,
+    pub variable: &'static str,
+    pub count: usize,
+    pub missing: usize,
+    pub mean: Option<f64>,
+    pub std_dev: Option<f64>,
+    pub min: Option<f64>,
+    pub q1: Option<f64>,
+    pub median: Option<f64>,
+    pub q3: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl CategoryStatistics {
+    pub fn rows(&self) -> Vec<StatisticsRow<'_>> {
+        [
+            ("salesrank", &self.salesrank),
+            ("rating", &self.rating),
+            ("review_count", &self.review_count),
+            ("helpful_votes", &self.helpful_votes),
+        ]
+        .into_iter()
+        .map(|(variable, stats)| StatisticsRow {
+            category: &self.category,
+            variable,
+            count: stats.count,
+            missing: stats.missing,
+            mean: stats.mean,
+            std_dev: stats.std_dev,
+            min: stats.min,
+            q1: stats.q1,
+            median: stats.median,
+            q3: stats.q3,
+            max: stats.max,
+        })
+        .collect()
+    }
+}
+
+// A graph node with its score from a centrality measure, joined with product metadata
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RankedProduct {
+    pub rank: usize,
+    pub node: usize,
+    pub product_id: u32,
+    pub asin: Option<String>,
+    pub title: Option<String>,
+    pub group: String,
+    pub salesrank: Option<u32>,
+    pub avg_rating: Option<f32>,
+    pub score: f64,
+}
+
+// Hub and authority scores of one graph node, joined with product metadata
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HitsProduct {
+    pub node: usize,
+    pub product_id: u32,
+    pub asin: Option<String>,
+    pub title: Option<String>,
+    pub group: String,
+    pub salesrank: Option<u32>,
+    pub avg_rating: Option<f32>,
+    pub hub: f64,
+    pub authority: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GraphBuildReport {
+    pub resolved_references: usize,
+    pub dangling_references: usize,
+    // Unknown ASINs, each with its placeholder node when `DanglingPolicy::External` is used
+    pub dangling_asins: Vec<(String, Option<NodeIndex>)>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -36,12 +274,263 @@ pub struct Review {
 pub struct AmazonDataCleaner {
     pub filepath: String,
     pub data: Vec<Product>, 
-    
+    // Built on first use and reset whenever `data` is reloaded or cleaned
+    asin_index: OnceCell<AsinIndex>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Category {
-    pub name: String,
+// A field that could not be parsed, with the line it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub field: &'static str,
+    pub raw: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: invalid {} value {:?}", self.line, self.field, self.raw)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "failed to read metadata: {}", e),
+            ReadError::Parse(e) => write!(f, "failed to parse metadata: {}", e),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(e: ParseError) -> Self {
+        ReadError::Parse(e)
+    }
+}
+
+// Strict stops at the first malformed field; lenient leaves the field empty and
+// records a warning in the `ParseReport`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    Strict,
+    #[default]
+    Lenient,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    pub lines_read: usize,
+    pub products_read: usize,
+    pub warnings: Vec<ParseError>,
+}
+
+impl ParseReport {
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+// Yields one product at a time from the SNAP metadata format. The reader only holds the
+// product being parsed; products come out uncleaned, since `clean_data` imputes from the
+// largest salesrank in the whole file.
+pub struct ProductReader<R: BufRead> {
+    lines: Lines<R>,
+    product: Product,
+    review_regex: Regex,
+    mode: ParseMode,
+    report: ParseReport,
+    finished: bool,
+    // The current record's `Id:` was malformed, so it is dropped in lenient mode
+    skip_record: bool,
+}
+
+impl<R: BufRead> ProductReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_mode(reader, ParseMode::default())
+    }
+
+    pub fn with_mode(reader: R, mode: ParseMode) -> Self {
+        // Regex to match date, customer ID, rating, votes, and helpful counts
+        let review_regex = Regex::new(r"(\d{4})-(\d{1,2})-(\d{1,2})\s+(?:customer|cutomer):\s+(\S+)\s+rating:\s+(\d+)\s+votes:\s+(\d+)\s+helpful:\s+(\d+)")
+            .expect("review regex is valid");
+        ProductReader {
+            lines: reader.lines(),
+            product: Product::default(),
+            review_regex,
+            mode,
+            report: ParseReport::default(),
+            finished: false,
+            skip_record: false,
+        }
+    }
+
+    pub fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> ParseReport {
+        self.report
+    }
+
+    // Read every remaining product and return them together with the parse report
+    pub fn collect_with_report(mut self) -> Result<(Vec<Product>, ParseReport), ReadError> {
+        let products = self.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok((products, self.into_report()))
+    }
+
+    fn parse_field<T: FromStr>(&self, field: &'static str, raw: &str) -> Result<T, ParseError> {
+        let raw = raw.trim();
+        raw.parse().map_err(|_| ParseError {
+            line: self.report.lines_read,
+            field,
+            raw: raw.to_string(),
+        })
+    }
+
+    // Apply one non-`Id:` line to the product currently being read
+    fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        if let Some(asin) = line.strip_prefix("ASIN: ") {
+            self.product.asin = Some(asin.trim().to_string());
+        } else if let Some(title) = line.strip_prefix("title: ") {
+            self.product.title = Some(title.trim().to_string());
+        } else if let Some(group) = line.strip_prefix("group: ") {
+            self.product.group = Some(group.trim().to_string());
+        } else if let Some(salesrank) = line.strip_prefix("salesrank: ") {
+            self.product.salesrank = Some(self.parse_field("salesrank", salesrank)?);
+        } else if let Some(similar) = line.strip_prefix("similar: ") {
+            self.product.similar = similar
+                .split_whitespace()
+                .skip(1)
+                .map(|s| s.to_string())
+                .collect();
+        } else if let Some(categories) = line.strip_prefix("categories: ") {
+            self.product.categories = Some(self.parse_field("categories", categories)?);
+        } else if line == "discontinued product" {
+            self.product.status = ProductStatus::Discontinued;
+        } else if line.starts_with('|') {
+            self.product.category_list.push(line.to_string());
+        } else if let Some(totals) = line.strip_prefix("reviews: total: ") {
+            // e.g. "reviews: total: 2  downloaded: 2  avg rating: 4.5"
+            let malformed = || ParseError {
+                line: self.report.lines_read,
+                field: "reviews",
+                raw: line.to_string(),
+            };
+            let (total, rest) = totals.split_once("downloaded:").ok_or_else(malformed)?;
+            let (downloaded, rating) = rest.split_once("avg rating:").ok_or_else(malformed)?;
+            let total_reviews = self.parse_field("total reviews", total)?;
+            let downloaded_reviews = self.parse_field("downloaded reviews", downloaded)?;
+            let avg_rating = self.parse_field("avg rating", rating)?;
+            self.product.total_reviews = Some(total_reviews);
+            self.product.downloaded_reviews = Some(downloaded_reviews);
+            self.product.avg_rating = Some(avg_rating);
+        } else if let Some(caps) = self.review_regex.captures(line) {
+            let review = Review {
+                date: format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]),
+                customer: caps[4].to_string(),
+                rating: self.parse_field("review rating", &caps[5])?,
+                votes: self.parse_field("review votes", &caps[6])?,
+                helpful: self.parse_field("review helpful", &caps[7])?,
+            };
+            self.product.reviews.push(review);
+        }
+        Ok(())
+    }
+
+    // In strict mode the error ends the stream, in lenient mode it becomes a warning
+    fn handle_error(&mut self, error: ParseError) -> Option<ReadError> {
+        match self.mode {
+            ParseMode::Strict => {
+                self.finished = true;
+                Some(error.into())
+            }
+            ParseMode::Lenient => {
+                self.report.warnings.push(error);
+                None
+            }
+        }
+    }
+
+    fn finish_product(&mut self, next_product: Product, skip_next: bool) -> Option<Product> {
+        let product = std::mem::replace(&mut self.product, next_product);
+        let skipped = std::mem::replace(&mut self.skip_record, skip_next);
+        // Records before the first `Id:` (the file header) have no ASIN
+        if product.asin.is_some() && !skipped {
+            self.report.products_read += 1;
+            Some(product)
+        } else {
+            None
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ProductReader<R> {
+    type Item = Result<Product, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        while let Some(line) = self.lines.next() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e.into()));
+                }
+            };
+            self.report.lines_read += 1;
+            let line = line.trim();
+
+            if let Some(id) = line.strip_prefix("Id: ") {
+                // A record without a valid id is dropped rather than given a made-up one
+                let (id, skip) = match self.parse_field("Id", id) {
+                    Ok(id) => (id, false),
+                    Err(e) => match self.handle_error(e) {
+                        Some(e) => return Some(Err(e)),
+                        None => (0, true),
+                    },
+                };
+                let next_product = Product {
+                    id,
+                    ..Product::default()
+                };
+                if let Some(product) = self.finish_product(next_product, skip) {
+                    return Some(Ok(product));
+                }
+            } else if let Err(e) = self.parse_line(line) {
+                if let Some(e) = self.handle_error(e) {
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        self.finished = true;
+        self.finish_product(Product::default(), false).map(Ok)
+    }
 }
 
 impl AmazonDataCleaner {
@@ -49,116 +538,126 @@ impl AmazonDataCleaner {
         AmazonDataCleaner {
             filepath: filepath.to_string(),
             data: Vec::new(),
+            asin_index: OnceCell::new(),
         }
     }
 
-    pub fn load_data(&mut self) -> Result<(), Box<dyn Error>> {
+    // Wrap products that are already in memory, e.g. a random sample
+    pub fn from_products(data: Vec<Product>) -> Self {
+        AmazonDataCleaner {
+            filepath: String::new(),
+            data,
+            asin_index: OnceCell::new(),
+        }
+    }
+
+    // The ASIN lookup shared by every graph builder
+    pub fn asin_index(&self) -> &AsinIndex {
+        self.asin_index.get_or_init(|| AsinIndex::build(&self.data))
+    }
+
+    // Stream products from the file one at a time instead of loading all of them
+    pub fn stream_products(&self) -> Result<ProductReader<BufReader<File>>, Box<dyn Error>> {
+        self.stream_products_with_mode(ParseMode::default())
+    }
+
+    pub fn stream_products_with_mode(&self, mode: ParseMode) -> Result<ProductReader<BufReader<File>>, Box<dyn Error>> {
         let file = File::open(&self.filepath)?;
-        let reader = BufReader::new(file);
-        let mut products = Vec::new();
-        let mut product = Product {
-            id: 0,
-            asin: None,
-            title: None,
-            group: None,
-            salesrank: None,
-            similar: Vec::new(),
-            categories: None,
-            category_list: Vec::new(),
-            total_reviews: None,
-            downloaded_reviews: None,
-            avg_rating: None,
-            reviews: Vec::new(),
-        };
-    
-        // Updated Regex to match date, customer ID, rating, votes, and helpful counts
-        let review_regex = Regex::new(r"(\d{4})-(\d{1,2})-(\d{1,2})\s+(?:customer|cutomer):\s+(\S+)\s+rating:\s+(\d+)\s+votes:\s+(\d+)\s+helpful:\s+(\d+)")?;
-    
-        for line in reader.lines() {
-            let line = line?;
-            let line = line.trim();
-    
-            if line.starts_with("Id: ") {
-                if product.asin.is_some() {
-                    products.push(product);
-                }
-                product = Product {
-                    id: line[4..].trim().parse().unwrap_or_default(),
-                    asin: None,
-                    title: None,
-                    group: None,
-                    salesrank: None,
-                    similar: Vec::new(),
-                    categories: None,
-                    category_list: Vec::new(),
-                    total_reviews: None,
-                    downloaded_reviews: None,
-                    avg_rating: None,
-                    reviews: Vec::new(),
-                };
-            } else if line.starts_with("ASIN: ") {
-                product.asin = Some(line[6..].trim().to_string());
-            } else if line.starts_with("title: ") {
-                product.title = Some(line[7..].trim().to_string());
-            } else if line.starts_with("group: ") {
-                product.group = Some(line[7..].trim().to_string());
-            } else if line.starts_with("salesrank: ") {
-                product.salesrank = Some(line[10..].trim().parse().unwrap_or_default());
-            } else if line.starts_with("similar: ") {
-                product.similar = line[9..]
-                    .trim()
-                    .split_whitespace()
-                    .skip(1)
-                    .map(|s| s.to_string())
-                    .collect();
-            } else if line.starts_with("categories: ") {
-                product.categories = Some(line[12..].trim().parse().unwrap_or_default());
-            } else if line.starts_with("|") {
-                product.category_list.push(line.to_string());
-            } else if line.starts_with("reviews: total: ") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 9 {
-                    product.total_reviews = Some(parts[2].parse().unwrap_or_default());
-                    product.downloaded_reviews = Some(parts[5].parse().unwrap_or_default());
-                    product.avg_rating = Some(parts[8].parse().unwrap_or_default());
-                }
-            } else if review_regex.is_match(line) {
-                let caps = review_regex.captures(line).unwrap();
-                let review = Review {
-                    date: format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]),
-                    customer: caps[4].to_string(),
-                    rating: caps[5].parse().unwrap_or_default(),
-                    votes: caps[6].parse().unwrap_or_default(),
-                    helpful: caps[7].parse().unwrap_or_default(),
-                };
-                product.reviews.push(review);
+        Ok(ProductReader::with_mode(BufReader::new(file), mode))
+    }
+
+    // Lenient load: malformed fields are left empty and listed in the returned report
+    pub fn load_data(&mut self) -> Result<ParseReport, Box<dyn Error>> {
+        self.load_data_with_mode(ParseMode::Lenient)
+    }
+
+    pub fn load_data_with_mode(&mut self, mode: ParseMode) -> Result<ParseReport, Box<dyn Error>> {
+        let (products, report) = self.stream_products_with_mode(mode)?.collect_with_report()?;
+        self.data = products;
+        self.asin_index = OnceCell::new();
+        Ok(report)
+    }
+
+    // Save the (cleaned) data and its ASIN index, tagged with the source file's fingerprint
+    pub fn save_snapshot(&self, snapshot_path: &str) -> Result<(), Box<dyn Error>> {
+        self.save_snapshot_of(snapshot_path, &SourceFingerprint::of(&self.filepath)?)
+    }
+
+    fn save_snapshot_of(&self, snapshot_path: &str, source: &SourceFingerprint) -> Result<(), Box<dyn Error>> {
+        write_snapshot(snapshot_path, source, &self.data, self.asin_index())
+    }
+
+    // Returns false, leaving `data` untouched, if there is no snapshot of the current source file
+    pub fn load_snapshot(&mut self, snapshot_path: &str) -> Result<bool, Box<dyn Error>> {
+        self.load_snapshot_of(snapshot_path, &SourceFingerprint::of(&self.filepath)?)
+    }
+
+    fn load_snapshot_of(&mut self, snapshot_path: &str, source: &SourceFingerprint) -> Result<bool, Box<dyn Error>> {
+        match read_snapshot(snapshot_path, source)? {
+            Some(snapshot) => {
+                self.data = snapshot.products;
+                self.asin_index = OnceCell::from(snapshot.asin_index);
+                Ok(true)
             }
+            None => Ok(false),
         }
-    
-        if product.asin.is_some() {
-            products.push(product);
+    }
+
+    // Load and clean from the snapshot if it is current, otherwise parse the source and
+    // refresh the snapshot. The parse report is `None` when the snapshot was used.
+    pub fn load_cached(&mut self, snapshot_path: &str) -> Result<Option<ParseReport>, Box<dyn Error>> {
+        // Fingerprinted once, before parsing, so a source edited mid-parse leaves a stale snapshot
+        let source = SourceFingerprint::of(&self.filepath)?;
+        if self.load_snapshot_of(snapshot_path, &source)? {
+            return Ok(None);
         }
-    
-        self.data = products;
-        Ok(())
+        let report = self.load_data()?;
+        self.clean_data();
+        self.save_snapshot_of(snapshot_path, &source)?;
+        Ok(Some(report))
     }
-    
+
     pub fn clean_data(&mut self) {
         self.data.retain(|product| product.asin.is_some());
+        self.asin_index = OnceCell::new();
 
-        let max_salesrank = self.data.iter().filter_map(|p| p.salesrank).max().unwrap_or(0);
+        let max_salesrank = self.data.iter().filter_map(|p| p.observed_salesrank()).max().unwrap_or(0);
         for product in &mut self.data {
-            if product.title.is_none() {
-                product.title = Some("Unknown".to_string());
-            }
-            if product.salesrank.is_none() {
-                product.salesrank = Some(max_salesrank + 1);
-            }
+            Self::clean_product(product, max_salesrank + 1);
+        }
+    }
+
+    // Clean a single product, so streamed products can be cleaned without collecting them first.
+    // Discontinued products are left as they are: they never had a title or salesrank.
+    pub fn clean_product(product: &mut Product, fallback_salesrank: u32) {
+        if product.status == ProductStatus::Discontinued {
+            return;
+        }
+        if product.title.is_none() {
+            product.title = Some("Unknown".to_string());
+        }
+        if product.salesrank.is_none() {
+            product.salesrank = Some(fallback_salesrank);
+            product.salesrank_imputed = true;
         }
     }
 
+    pub fn category_tree(&self) -> CategoryTree {
+        CategoryTree::from_products(&self.data)
+    }
+
+    // Like grouping by `group`, but at any depth of the category hierarchy
+    pub fn group_by_category_depth(&self, depth: usize) -> HashMap<Category, Vec<&Product>> {
+        group_products_at_depth(&self.data, depth)
+    }
+
     pub fn random_sample(&self, sample_size: usize) -> Vec<Product> {
-        let mut rng = rand::thread_rng();
+        self.random_sample_seeded(sample_size, random_seed())
+    }
+
+    // Same seed and same data give the same products in the same order
+    pub fn random_sample_seeded(&self, sample_size: usize, seed: u64) -> Vec<Product> {
+        let mut rng = seeded_rng(seed);
         let sampled_data: Vec<Product> = self
             .data
             .choose_multiple(&mut rng, sample_size)
@@ -167,37 +666,53 @@ impl AmazonDataCleaner {
         sampled_data
     }
 
-    pub fn summarize_top_categories(&self) -> Vec<(String, usize, f64, Option<f64>)> {
-        // Count the number of products in each category
-        let mut category_counts: HashMap<String, usize> = HashMap::new();
-        for product in &self.data {
-            if let Some(category) = product.group.clone() {
-                *category_counts.entry(category).or_insert(0) += 1;
-            }
-        }
-    
-        // Sort categories by count in descending order
-        let mut sorted_categories: Vec<_> = category_counts.into_iter().collect();
-        sorted_categories.sort_by(|a, b| b.1.cmp(&a.1)); // Sort by count in descending order
-    
-        // Take the top 3 categories
-        let top_categories = sorted_categories.into_iter().take(3);
+    // Sample with any `SamplingStrategy`; feed the result to `from_products` to build graphs
+    pub fn sample_with(&self, strategy: &SamplingStrategy, seed: u64) -> Result<Vec<Product>, String> {
+        strategy.sample(&self.data, self.asin_index(), seed)
+    }
+
+    // Stratified by group or category, with the weights needed to reweight estimates
+    pub fn random_sample_stratified(
+        &self,
+        stratify_by: GroupBy,
+        allocation: &Allocation,
+        sample_size: usize,
+        seed: u64,
+    ) -> StratifiedSample {
+        stratified_sample(&self.data, stratify_by, allocation, sample_size, seed)
+    }
+
+    // Reservoir sampling over a product stream: only `sample_size` products are kept in memory
+    pub fn reservoir_sample<I>(products: I, sample_size: usize, seed: u64) -> Vec<Product>
+    where
+        I: IntoIterator<Item = Product>,
+    {
+        let mut rng = seeded_rng(seed);
+        products.into_iter().choose_multiple(&mut rng, sample_size)
+    }
+
+    pub fn summarize_top_n_categories(&self, top_n: usize) -> Vec<CategorySummary> {
+        self.summarize_categories(&SummaryOptions {
+            top_n: Some(top_n),
+            ..SummaryOptions::default()
+        })
+    }
+
+    pub fn summarize_categories(&self, options: &SummaryOptions) -> Vec<CategorySummary> {
+        let asin_index = self.asin_index();
     
-        // Collect summary statistics for the top categories
+        // Collect summary statistics for every category
         let mut summaries = Vec::new();
-        for (category, count) in top_categories {
-            let products_in_category: Vec<_> = self
-                .data
-                .iter()
-                .filter(|p| p.group.as_ref() == Some(&category))
-                .collect();
+        for (category, positions) in self.products_by_category(options.group_by) {
+            let count = positions.len();
+            let products_in_category: Vec<_> = positions.iter().map(|&p| &self.data[p]).collect();
     
-            let avg_sales_rank: f64 = products_in_category
+            let sales_ranks: Vec<f64> = products_in_category
                 .iter()
-                .filter_map(|p| p.salesrank)
+                .filter_map(|p| p.observed_salesrank())
                 .map(|r| r as f64)
-                .sum::<f64>()
-                / count as f64;
+                .collect();
+            let avg_sales_rank = sales_ranks.iter().sum::<f64>() / sales_ranks.len() as f64;
     
             let avg_rating: f64 = products_in_category
                 .iter()
@@ -208,73 +723,127 @@ impl AmazonDataCleaner {
                     .iter()
                     .flat_map(|p| p.reviews.iter())
                     .count() as f64;
+
+            let review_volume: u64 = products_in_category
+                .iter()
+                .map(|p| p.total_reviews.unwrap_or(p.reviews.len() as u32) as u64)
+                .sum();
+
+            // Out-degree in the global graph: similar products that are in the data
+            let total_degree: usize = products_in_category
+                .iter()
+                .map(|p| p.similar.iter().filter(|a| asin_index.position(a).is_some()).count())
+                .sum();
     
-            summaries.push((
+            summaries.push(CategorySummary {
                 category,
-                count,
-                avg_sales_rank,
-                if avg_rating.is_nan() { None } else { Some(avg_rating) },
-            ));
+                product_count: count,
+                avg_salesrank: if avg_sales_rank.is_nan() { None } else { Some(avg_sales_rank) },
+                avg_rating: if avg_rating.is_nan() { None } else { Some(avg_rating) },
+                review_volume,
+                avg_degree: total_degree as f64 / count as f64,
+            });
+        }
+
+        options.rank_by.sort(&mut summaries);
+        if let Some(top_n) = options.top_n {
+            summaries.truncate(top_n);
         }
-        
         summaries
-        
     }
     
-    pub fn create_graphs_for_top_categories(
-        &self,
-        top_categories: Vec<(String, usize, f64, Option<f64>)>,
-    ) -> HashMap<String, Graph<(u32, String), ()>> {
+    // Positions of the products in each category (a product can be in several at a category depth)
+    fn products_by_category(&self, group_by: GroupBy) -> HashMap<String, Vec<usize>> {
+        let mut products_by_category: HashMap<String, Vec<usize>> = HashMap::new();
+        for (position, product) in self.data.iter().enumerate() {
+            for category in group_by.labels_of(product) {
+                products_by_category.entry(category).or_default().push(position);
+            }
+        }
+        products_by_category
+    }
+
+    // Full descriptive statistics for the categories `summarize_categories` selects, in
+    // the same order. Imputed salesranks count as missing unless `include_imputed` is set.
+    pub fn describe_categories(&self, options: &SummaryOptions, include_imputed: bool) -> Vec<CategoryStatistics> {
+        let mut products_by_category = self.products_by_category(options.group_by);
+        self.summarize_categories(options)
+            .into_iter()
+            .map(|summary| {
+                let positions = products_by_category.remove(&summary.category).unwrap_or_default();
+                let products: Vec<&Product> = positions.iter().map(|&p| &self.data[p]).collect();
+                let salesrank = |p: &Product| {
+                    if include_imputed { p.salesrank } else { p.observed_salesrank() }
+                };
+                CategoryStatistics {
+                    salesrank: DescriptiveStats::from_options(products.iter().map(|p| salesrank(p).map(f64::from))),
+                    // SNAP writes `avg rating: 0` for products without reviews
+                    rating: DescriptiveStats::from_options(products.iter().map(|p| {
+                        p.avg_rating
+                            .filter(|_| p.total_reviews.unwrap_or(0) > 0)
+                            .map(f64::from)
+                    })),
+                    review_count: DescriptiveStats::from_options(
+                        products.iter().map(|p| p.total_reviews.map(f64::from)),
+                    ),
+                    helpful_votes: DescriptiveStats::from_options(products.iter().map(|p| {
+                        p.total_reviews
+                            .map(|_| p.reviews.iter().map(|r| r.helpful as f64).sum())
+                    })),
+                    category: summary.category,
+                    product_count: summary.product_count,
+                }
+            })
+            .collect()
+    }
+
+    pub fn create_category_graphs(&self, categories: &[String]) -> HashMap<String, ProductGraph> {
+        self.create_category_graphs_by(GroupBy::Group, categories)
+    }
+
+    // Per-category graphs at any level; `categories` are labels from `GroupBy::labels_of`
+    pub fn create_category_graphs_by(&self, group_by: GroupBy, categories: &[String]) -> HashMap<String, ProductGraph> {
         let mut category_graphs = HashMap::new();
-        let mut id_to_node_global = HashMap::new(); // Global mapping of product ID to node indices
-    
-        // Populate the global graph with all products
-        for product in &self.data {
-            let category = product.group.clone().unwrap_or_else(|| "Unknown".to_string());
-            let node_index = id_to_node_global
-                .entry(product.id)
-                .or_insert_with(|| Graph::<(u32, String), ()>::new().add_node((product.id, category.clone())));
+        let asin_index = self.asin_index();
+
+        // Labels are worked out once per product; each bucket keeps data order
+        let mut buckets: HashMap<&str, Vec<usize>> = categories.iter().map(|c| (c.as_str(), Vec::new())).collect();
+        for (position, product) in self.data.iter().enumerate() {
+            for label in group_by.labels_of(product) {
+                if let Some(bucket) = buckets.get_mut(label.as_str()) {
+                    bucket.push(position);
+                }
+            }
         }
     
         // Build category-specific graphs
-        for (category, _, _, _) in top_categories {
-            let mut graph = Graph::<(u32, String), ()>::new();
-            let mut id_to_node_local = HashMap::new();
-    
-            // Filter products belonging to the current category
-            let products_in_category: Vec<_> = self
-                .data
-                .iter()
-                .filter(|p| p.group.as_ref() == Some(&category))
-                .collect();
+        for category in categories {
+            let mut graph = ProductGraph::new();
+            // Members in data order, so edges are added in the same order on every run
+            let mut members = Vec::new();
+            let mut position_to_node_local = HashMap::new();
     
             // Add nodes for all products in the category
-            for product in &products_in_category {
-                let node_index = graph.add_node((product.id, category.clone()));
-                id_to_node_local.insert(product.id, node_index);
+            for &position in &buckets[category.as_str()] {
+                let node_index = graph.add_node(ProductNode::from_product(&self.data[position]));
+                members.push((position, node_index));
+                position_to_node_local.insert(position, node_index);
             }
     
             // Add edges based on "similar" ASINs
-            for product in &products_in_category {
-                if let Some(&source_node) = id_to_node_local.get(&product.id) {
-                    for similar_asin in &product.similar {
-                        let similar_asin_normalized = similar_asin.trim().to_lowercase(); // Normalize similar ASIN
-    
-                        // Find the matching product in the global data
-                        if let Some(similar_product) = self
-                            .data
-                            .iter()
-                            .find(|p| p.asin.as_deref().map(|a| a.trim().to_lowercase()) == Some(similar_asin_normalized.clone()))
-                        {
-                            if let Some(&target_node) = id_to_node_local.get(&similar_product.id) {
-                                // Add edge only if both source and target nodes are valid
-                                graph.add_edge(source_node, target_node, ());
-                            }
-                        }
+            for &(position, source_node) in &members {
+                for (rank, similar_asin) in self.data[position].similar.iter().enumerate() {
+                    // Add edge only if the similar product is also in this category
+                    if let Some(target_node) = asin_index
+                        .position(similar_asin)
+                        .and_then(|p| position_to_node_local.get(&p))
+                    {
+                        graph.add_edge(source_node, *target_node, CoPurchaseEdge::forward(rank));
                     }
                 }
             }
     
+            mark_reciprocated(&mut graph);
             category_graphs.insert(category.clone(), graph);
         }
     
@@ -296,44 +865,206 @@ impl AmazonDataCleaner {
         }
     }*/
     
-    pub fn create_global_graph(&self) -> Graph<(u32, String), ()> {
-        let mut global_graph = Graph::<(u32, String), ()>::new();
-        let mut id_to_node = HashMap::new();
+    pub fn create_global_graph(&self) -> ProductGraph {
+        self.create_global_graph_with(DanglingPolicy::Skip).0
+    }
+
+    pub fn create_global_graph_with(&self, policy: DanglingPolicy) -> (ProductGraph, GraphBuildReport) {
+        let mut global_graph = ProductGraph::with_capacity(self.data.len(), 0);
+        let asin_index = self.asin_index();
+        let mut dangling = DanglingLinks::new(policy);
     
-        // Add all products as nodes to the global graph
+        // Add all products as nodes to the global graph, so node index == data position
         for product in &self.data {
-            let category = product.group.clone().unwrap_or("Unknown".to_string());
-            let node_index = global_graph.add_node((product.id, category.clone()));
-            id_to_node.insert(product.id, node_index);
+            global_graph.add_node(ProductNode::from_product(product));
         }
     
         // Add edges for all "similar" products
-        for product in &self.data {
-            if let Some(&source_node) = id_to_node.get(&product.id) {
-                for similar_asin in &product.similar {
-                    let similar_asin_normalized = similar_asin.trim().to_lowercase();
-    
-                    // Match similar product in the dataset
-                    if let Some(similar_product) = self
-                        .data
-                        .iter()
-                        .find(|p| p.asin.as_deref().map(|a| a.trim().to_lowercase()) == Some(similar_asin_normalized.clone()))
-                    {
-                        if let Some(&target_node) = id_to_node.get(&similar_product.id) {
-                            global_graph.add_edge(source_node, target_node, ());
-                        }
-                    }
+        for (position, product) in self.data.iter().enumerate() {
+            let source_node = NodeIndex::new(position);
+            for (rank, similar_asin) in product.similar.iter().enumerate() {
+                match asin_index.position(similar_asin) {
+                    Some(target) => dangling.link(&mut global_graph, source_node, NodeIndex::new(target), rank),
+                    None => dangling.link_unknown(&mut global_graph, source_node, similar_asin, rank),
                 }
             }
         }
     
-        global_graph
+        mark_reciprocated(&mut global_graph);
+        (global_graph, dangling.report)
+    }
+
+    // Title of the product behind a node of any graph built from this data
+    pub fn node_title(&self, node: &ProductNode) -> Option<String> {
+        let position = self.asin_index().position(node.asin.as_deref()?)?;
+        self.data[position].title.clone()
+    }
+
+    // Labels of every node of a graph built from this data, for mixing matrices. Groups
+    // come from the nodes themselves, so placeholder nodes keep their group too.
+    pub fn node_labels(&self, graph: &ProductGraph, group_by: GroupBy) -> Vec<Vec<String>> {
+        let asin_index = self.asin_index();
+        graph
+            .node_weights()
+            .map(|node| match group_by {
+                GroupBy::Group => vec![node.group.clone()],
+                GroupBy::CategoryDepth(_) => node
+                    .asin
+                    .as_deref()
+                    .and_then(|asin| asin_index.position(asin))
+                    .map_or_else(Vec::new, |position| group_by.labels_of(&self.data[position])),
+            })
+            .collect()
+    }
+
+    // Join (node, score) pairs from any graph built from this data with the product metadata
+    pub fn rank_products(&self, graph: &ProductGraph, scores: &[(NodeIndex, f64)]) -> Vec<RankedProduct> {
+        scores
+            .iter()
+            .enumerate()
+            .map(|(rank, &(node, score))| {
+                let product_node = &graph[node];
+                RankedProduct {
+                    rank: rank + 1,
+                    node: node.index(),
+                    product_id: product_node.id,
+                    asin: product_node.asin.clone(),
+                    title: self.node_title(product_node),
+                    group: product_node.group.clone(),
+                    salesrank: product_node.salesrank,
+                    avg_rating: product_node.avg_rating,
+                    score,
+                }
+            })
+            .collect()
+    }
+
+    pub fn hits_products(&self, graph: &ProductGraph, hubs: &[f64], authorities: &[f64]) -> Vec<HitsProduct> {
+        graph
+            .node_indices()
+            .map(|node| {
+                let product_node = &graph[node];
+                HitsProduct {
+                    node: node.index(),
+                    product_id: product_node.id,
+                    asin: product_node.asin.clone(),
+                    title: self.node_title(product_node),
+                    group: product_node.group.clone(),
+                    salesrank: product_node.salesrank,
+                    avg_rating: product_node.avg_rating,
+                    hub: hubs[node.index()],
+                    authority: authorities[node.index()],
+                }
+            })
+            .collect()
+    }
+
+    // Build the global graph in a single pass over a product stream, without collecting the
+    // products. Memory still grows with the data: every node payload, ASIN map entry and
+    // `similar` list is kept until the edges are resolved; only reviews, categories and
+    // titles are dropped as they stream past. Nodes carry observed salesranks, so the
+    // products need no cleaning first.
+    pub fn create_global_graph_from_stream<I>(products: I) -> ProductGraph
+    where
+        I: IntoIterator<Item = Product>,
+    {
+        Self::create_global_graph_from_stream_with(products, DanglingPolicy::Skip).0
+    }
+
+    pub fn create_global_graph_from_stream_with<I>(
+        products: I,
+        policy: DanglingPolicy,
+    ) -> (ProductGraph, GraphBuildReport)
+    where
+        I: IntoIterator<Item = Product>,
+    {
+        let mut global_graph = ProductGraph::new();
+        let mut asin_index = AsinIndex::default();
+        let mut pending_edges = Vec::new();
+        let mut dangling = DanglingLinks::new(policy);
+
+        for product in products {
+            let node_index = global_graph.add_node(ProductNode::from_product(&product));
+            if let Some(asin) = &product.asin {
+                asin_index.insert(asin, node_index.index());
+            }
+            pending_edges.push((node_index, product.similar));
+        }
+
+        // Resolve the similar ASINs once every product has a node
+        for (source_node, similar) in pending_edges {
+            for (rank, similar_asin) in similar.iter().enumerate() {
+                match asin_index.position(similar_asin) {
+                    Some(target) => dangling.link(&mut global_graph, source_node, NodeIndex::new(target), rank),
+                    None => dangling.link_unknown(&mut global_graph, source_node, similar_asin, rank),
+                }
+            }
+        }
+
+        mark_reciprocated(&mut global_graph);
+        (global_graph, dangling.report)
     }
     
 }
+
+// Edges of a global graph build, applying the `DanglingPolicy` to unknown ASINs
+struct DanglingLinks {
+    policy: DanglingPolicy,
+    report: GraphBuildReport,
+    // Normalized ASIN to its entry in `report.dangling_asins`
+    seen: HashMap<String, usize>,
+    placeholders: u32,
+}
+
+impl DanglingLinks {
+    fn new(policy: DanglingPolicy) -> Self {
+        DanglingLinks {
+            policy,
+            report: GraphBuildReport::default(),
+            seen: HashMap::new(),
+            placeholders: 0,
+        }
+    }
+
+    fn link(&mut self, graph: &mut ProductGraph, source: NodeIndex, target: NodeIndex, rank: usize) {
+        self.report.resolved_references += 1;
+        graph.add_edge(source, target, CoPurchaseEdge::forward(rank));
+    }
+
+    fn link_unknown(&mut self, graph: &mut ProductGraph, source: NodeIndex, asin: &str, rank: usize) {
+        self.report.dangling_references += 1;
+        let normalized = AsinIndex::normalize(asin);
+        let entry = match self.seen.get(&normalized) {
+            Some(&entry) => entry,
+            None => {
+                let placeholder = (self.policy == DanglingPolicy::External).then(|| {
+                    let id = EXTERNAL_PRODUCT_ID - self.placeholders;
+                    self.placeholders += 1;
+                    graph.add_node(ProductNode::external(asin, id))
+                });
+                self.report.dangling_asins.push((asin.trim().to_string(), placeholder));
+                self.seen.insert(normalized, self.report.dangling_asins.len() - 1);
+                self.report.dangling_asins.len() - 1
+            }
+        };
+        if let Some(placeholder) = self.report.dangling_asins[entry].1 {
+            graph.add_edge(source, placeholder, CoPurchaseEdge::forward(rank));
+        }
+    }
+}
     
 
 impl Product {
+    // The salesrank from the source file, ignoring any placeholder `clean_data` filled in
+    pub fn observed_salesrank(&self) -> Option<u32> {
+        self.salesrank.filter(|_| !self.salesrank_imputed)
+    }
+
+    // Each `category_list` line parsed into a root-first path
+    pub fn category_paths(&self) -> Vec<Vec<Category>> {
+        self.category_list.iter().map(|line| Category::parse_path(line)).collect()
+    }
+
     pub fn extract_features(&self) -> HashMap<String, f64> {
         let mut features = HashMap::new();
 
@@ -365,3 +1096,374 @@ impl Product {
     }
     
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{chain_products, sample_cleaner, sample_products, temp_dir, SAMPLE_META};
+    use petgraph::graph::Graph;
+    use petgraph::visit::EdgeRef;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    #[test]
+fn test_adjacency_list() {
+    let mut graph = Graph::<u32, ()>::new();
+
+    let node_a = graph.add_node(1); // Product 1
+    let node_b = graph.add_node(2); // Product 2
+    let node_c = graph.add_node(3); // Product 3
+
+    graph.add_edge(node_a, node_b, ()); // Product 1 -> Product 2
+    graph.add_edge(node_b, node_c, ()); // Product 2 -> Product 3
+
+    let expected_adjacency = vec![
+        (1, vec![2]),
+        (2, vec![3]),
+        (3, vec![]),
+    ];
+
+    for (expected_product, expected_neighbors) in expected_adjacency {
+        let node_index = graph
+            .node_indices()
+            .find(|&n| graph[n] == expected_product)
+            .expect("Node not found");
+        let neighbors: Vec<u32> = graph.neighbors(node_index).map(|n| graph[n]).collect();
+
+        assert_eq!(
+            neighbors, expected_neighbors,
+            "Mismatch for Product {}: Expected {:?}, Got {:?}",
+            expected_product, expected_neighbors, neighbors
+        );
+    }
+}
+
+    #[test]
+    fn test_product_reader_streams_products() {
+        let products = sample_products();
+
+        assert_eq!(products.len(), 3);
+        assert_eq!(products[0].asin.as_deref(), Some("0771044445"));
+        assert!(products[0].title.is_none());
+
+        let preaching = &products[1];
+        assert_eq!(preaching.id, 1);
+        assert_eq!(preaching.group.as_deref(), Some("Book"));
+        assert_eq!(preaching.salesrank, Some(396585));
+        assert_eq!(preaching.similar, vec!["0804215715", "0738700797"]);
+        assert_eq!(preaching.category_list.len(), 2);
+        assert_eq!(preaching.total_reviews, Some(2));
+        assert_eq!(preaching.downloaded_reviews, Some(2));
+        assert_eq!(preaching.reviews.len(), 2);
+        assert_eq!(preaching.reviews[0].customer, "A2JW67OY8U6HHK");
+        assert_eq!(products[2].reviews[0].helpful, 4);
+        assert_eq!(products[2].avg_rating, Some(4.5));
+    }
+
+    #[test]
+    fn test_lenient_mode_reports_malformed_fields() {
+        let corrupted = SAMPLE_META.replace("salesrank: 168596", "salesrank: 16x596");
+        let (products, report) = ProductReader::with_mode(Cursor::new(corrupted.as_str()), ParseMode::Lenient)
+            .collect_with_report()
+            .expect("lenient mode keeps going");
+        assert_eq!(products.len(), 3);
+        assert_eq!(products[2].salesrank, None);
+        assert_eq!(report.products_read, 3);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].line, 25);
+        assert_eq!(report.warnings[0].field, "salesrank");
+        assert_eq!(report.warnings[0].raw, "16x596");
+    }
+
+    #[test]
+    fn test_strict_mode_fails_on_malformed_fields() {
+        let corrupted = SAMPLE_META.replace("salesrank: 168596", "salesrank: 16x596");
+        let strict: Vec<_> = ProductReader::with_mode(Cursor::new(corrupted.as_str()), ParseMode::Strict).collect();
+        assert_eq!(strict.len(), 3);
+        assert!(strict[..2].iter().all(|p| p.is_ok()));
+        match &strict[2] {
+            Err(ReadError::Parse(e)) => assert_eq!(e.line, 25),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_malformed_id_drops_the_record() {
+        let bad_id = SAMPLE_META.replace("Id:   1", "Id:   x1");
+        let (products, report) = ProductReader::with_mode(Cursor::new(bad_id.as_str()), ParseMode::Lenient)
+            .collect_with_report()
+            .unwrap();
+        assert_eq!(products.iter().map(|p| p.id).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!((report.warnings[0].field, report.warnings[0].raw.as_str()), ("Id", "x1"));
+    }
+
+    #[test]
+    fn test_stream_graph_matches_loaded_graph() {
+        let products = sample_products();
+        let loaded = AmazonDataCleaner::from_products(products.clone()).create_global_graph();
+        let streamed = AmazonDataCleaner::create_global_graph_from_stream(products);
+
+        assert_eq!(streamed.node_count(), loaded.node_count());
+        assert_eq!(streamed.edge_count(), loaded.edge_count());
+        assert_eq!(streamed.edge_count(), 2); // 1 -> 2 and 2 -> 1
+    }
+
+    #[test]
+    fn test_category_graph_resolves_edges_within_the_group() {
+        let graphs = sample_cleaner().create_category_graphs(&["Book".to_string()]);
+        let book_graph = &graphs["Book"];
+        assert_eq!(book_graph.node_count(), 2);
+        assert_eq!(book_graph.edge_count(), 2);
+    }
+
+    #[test]
+    fn test_category_graph_edges_follow_data_order() {
+        // Edges follow the data order, so neighbour order is the same on every run
+        let chain = AmazonDataCleaner::from_products(chain_products(60));
+        let books = &chain.create_category_graphs(&["Book".to_string()])["Book"];
+        let sources: Vec<usize> = books.edge_references().map(|e| e.source().index()).collect();
+        assert!(sources.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(sources.len(), 29);
+    }
+
+    #[test]
+    fn test_clean_data_marks_discontinued_products() {
+        let mut cleaner = sample_cleaner();
+        cleaner.clean_data();
+
+        assert_eq!(cleaner.data[0].status, ProductStatus::Discontinued);
+        assert_eq!(cleaner.data[0].title, None);
+        assert_eq!(cleaner.data[0].salesrank, None);
+        assert_eq!(cleaner.data[1].status, ProductStatus::Active);
+    }
+
+    #[test]
+    fn test_dangling_references_are_skipped() {
+        let (graph, report) = sample_cleaner().create_global_graph_with(DanglingPolicy::Skip);
+        assert_eq!(graph.node_count(), 3);
+        assert_eq!(report.resolved_references, 2);
+        assert_eq!(report.dangling_references, 5);
+        assert!(report.dangling_asins.iter().all(|(_, node)| node.is_none()));
+    }
+
+    #[test]
+    fn test_dangling_references_become_external_nodes() {
+        let (graph, report) = sample_cleaner().create_global_graph_with(DanglingPolicy::External);
+        assert_eq!(graph.node_count(), 3 + 5);
+        assert_eq!(graph.edge_count(), 2 + 5);
+        let (asin, node) = &report.dangling_asins[0];
+        assert_eq!(asin, "0804215715");
+        assert_eq!(graph[node.unwrap()].group, EXTERNAL_GROUP);
+        let mut ids: Vec<u32> = graph.node_weights().map(|n| n.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 3 + 5, "placeholders share a product id");
+    }
+
+    #[test]
+    fn test_stream_graph_applies_the_dangling_policy() {
+        let cleaner = sample_cleaner();
+        let (graph, report) = cleaner.create_global_graph_with(DanglingPolicy::External);
+        let (streamed, streamed_report) =
+            AmazonDataCleaner::create_global_graph_from_stream_with(cleaner.data.clone(), DanglingPolicy::External);
+        assert_eq!((streamed.node_count(), streamed.edge_count()), (3 + 5, 2 + 5));
+        assert_eq!(streamed_report.dangling_references, report.dangling_references);
+        let node = report.dangling_asins[0].1.unwrap();
+        assert_eq!(streamed[node], graph[node]);
+    }
+
+    #[test]
+    fn test_group_by_category_depth() {
+        let cleaner = sample_cleaner();
+        let by_subject = cleaner.group_by_category_depth(3);
+        assert_eq!(by_subject.len(), 2);
+        assert!(by_subject.values().all(|products| products.len() == 1));
+    }
+
+    fn ids(sample: Vec<Product>) -> Vec<u32> {
+        sample.iter().map(|p| p.id).collect()
+    }
+
+    #[test]
+    fn test_seeded_sampling_is_reproducible() {
+        let cleaner = AmazonDataCleaner::from_products(chain_products(50));
+        let first = ids(cleaner.random_sample_seeded(10, 42));
+        assert_eq!(first.len(), 10);
+        assert_eq!(first, ids(cleaner.random_sample_seeded(10, 42)));
+        assert_ne!(first, ids(cleaner.random_sample_seeded(10, 43)));
+    }
+
+    #[test]
+    fn test_reservoir_sampling_is_reproducible() {
+        let products = chain_products(50);
+        let streamed = ids(AmazonDataCleaner::reservoir_sample(products.clone(), 10, 7));
+        assert_eq!(streamed.len(), 10);
+        assert_eq!(streamed, ids(AmazonDataCleaner::reservoir_sample(products, 10, 7)));
+    }
+
+    // Ten products: evens in Book with salesrank 100, odds in Music with 10, except 9 in
+    // Toy; product i has i reviews
+    fn ranked_cleaner() -> AmazonDataCleaner {
+        let mut products = chain_products(10);
+        for (i, product) in products.iter_mut().enumerate() {
+            product.salesrank = Some(if i % 2 == 0 { 100 } else { 10 });
+            product.total_reviews = Some(i as u32);
+        }
+        products[9].group = Some("Toy".to_string());
+        AmazonDataCleaner::from_products(products)
+    }
+
+    fn categories(summaries: &[CategorySummary]) -> Vec<&str> {
+        summaries.iter().map(|s| s.category.as_str()).collect()
+    }
+
+    #[test]
+    fn test_summary_ranks_by_product_count() {
+        let options = SummaryOptions { top_n: None, ..SummaryOptions::default() };
+        let by_count = ranked_cleaner().summarize_categories(&options);
+        assert_eq!(categories(&by_count), vec!["Book", "Music", "Toy"]);
+        assert_eq!(by_count[1].product_count, 4);
+        assert_eq!(by_count[1].review_volume, 1 + 3 + 5 + 7);
+        // Both similar products of 1, 3, 5 and 7 are in the data; 8 only has 9
+        assert!((by_count[1].avg_degree - 2.0).abs() < 1e-9);
+        assert!((by_count[0].avg_degree - 9.0 / 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_summary_ranks_by_mean_salesrank() {
+        let by_salesrank = ranked_cleaner().summarize_categories(&SummaryOptions {
+            rank_by: RankBy::MeanSalesrank,
+            top_n: Some(2),
+            ..SummaryOptions::default()
+        });
+        assert_eq!(categories(&by_salesrank), vec!["Music", "Toy"]);
+    }
+
+    #[test]
+    fn test_summary_groups_by_category_depth() {
+        let cleaner = sample_cleaner();
+        let options = SummaryOptions {
+            group_by: GroupBy::CategoryDepth(3),
+            top_n: None,
+            ..SummaryOptions::default()
+        };
+        let by_subject = cleaner.summarize_categories(&options);
+        assert_eq!(categories(&by_subject), vec!["Christianity[12290]", "Earth-Based Religions[12472]"]);
+
+        let graphs = cleaner.create_category_graphs_by(options.group_by, &["Christianity[12290]".to_string()]);
+        assert_eq!(graphs["Christianity[12290]"].node_count(), 1);
+    }
+
+    #[test]
+    fn test_group_by_strata_take_the_first_label() {
+        let sample = sample_products();
+        assert_eq!(GroupBy::Group.stratum_of(&sample[0]), "Unknown");
+        assert_eq!(GroupBy::Group.stratum_of(&sample[1]), "Book");
+        // Both of product 1's paths pass through Clergy; its leaves differ
+        assert_eq!(GroupBy::CategoryDepth(5).labels_of(&sample[1]).len(), 2);
+        assert_eq!(GroupBy::CategoryDepth(5).stratum_of(&sample[1]), "Preaching[12368]");
+    }
+
+    // Book products 0 and 2 with salesranks 10 and 30, Music products 1 and 3 without; 0
+    // has a rating of 0 from no reviews and 2 has a real 4.0
+    fn imputed_cleaner() -> AmazonDataCleaner {
+        let mut products = chain_products(4);
+        products[0].salesrank = Some(10);
+        products[2].salesrank = Some(30);
+        products[2].total_reviews = Some(2);
+        products[2].avg_rating = Some(4.0);
+        products[0].total_reviews = Some(0);
+        products[0].avg_rating = Some(0.0);
+        let mut cleaner = AmazonDataCleaner::from_products(products);
+        cleaner.clean_data();
+        cleaner
+    }
+
+    #[test]
+    fn test_clean_data_imputes_missing_salesranks() {
+        let cleaner = imputed_cleaner();
+        assert!(cleaner.data[1].salesrank_imputed);
+        assert_eq!(cleaner.data[1].salesrank, Some(31));
+        assert_eq!(cleaner.data[1].observed_salesrank(), None);
+        assert!(!cleaner.data[0].salesrank_imputed);
+    }
+
+    #[test]
+    fn test_summaries_skip_imputed_salesranks() {
+        let summaries = imputed_cleaner().summarize_top_n_categories(2);
+        let books = summaries.iter().find(|s| s.category == "Book").unwrap();
+        assert_eq!(books.avg_salesrank, Some(20.0));
+        let music = summaries.iter().find(|s| s.category == "Music").unwrap();
+        assert_eq!(music.avg_salesrank, None);
+    }
+
+    #[test]
+    fn test_describe_categories_counts_missing_values() {
+        let statistics = imputed_cleaner().describe_categories(&SummaryOptions::default(), false);
+        let books = statistics.iter().find(|s| s.category == "Book").unwrap();
+        assert_eq!((books.salesrank.count, books.salesrank.missing), (2, 0));
+        assert_eq!((books.rating.count, books.rating.missing), (1, 1));
+        assert_eq!(books.rows().len(), 4);
+        let music = statistics.iter().find(|s| s.category == "Music").unwrap();
+        assert_eq!((music.salesrank.count, music.salesrank.missing), (0, 2));
+    }
+
+    #[test]
+    fn test_describe_categories_can_include_imputed_values() {
+        let with_imputed = imputed_cleaner().describe_categories(&SummaryOptions::default(), true);
+        let music = with_imputed.iter().find(|s| s.category == "Music").unwrap();
+        assert_eq!(music.salesrank.median, Some(31.0));
+    }
+
+    // `SAMPLE_META` written to a fresh directory, with the source and snapshot paths in it
+    fn snapshot_files(name: &str) -> (PathBuf, String, String) {
+        let dir = temp_dir(name);
+        let source = dir.join("meta.txt").to_str().unwrap().to_string();
+        std::fs::write(&source, SAMPLE_META).unwrap();
+        let snapshot = dir.join("meta.snapshot").to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&snapshot);
+        (dir, source, snapshot)
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let (dir, source, snapshot) = snapshot_files("snapshot-round-trip");
+        let mut cleaner = AmazonDataCleaner::new(&source);
+        assert!(cleaner.load_cached(&snapshot).unwrap().is_some(), "first load parses");
+
+        let mut cached = AmazonDataCleaner::new(&source);
+        assert!(cached.load_cached(&snapshot).unwrap().is_none(), "second load uses the snapshot");
+        assert_eq!(cached.data.len(), cleaner.data.len());
+        assert_eq!(cached.data[1].reviews.len(), 2);
+        assert_eq!(cached.asin_index().position("0738700797"), Some(2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_snapshot_is_reparsed_and_replaced() {
+        let (dir, source, snapshot) = snapshot_files("snapshot-truncated");
+        AmazonDataCleaner::new(&source).load_cached(&snapshot).unwrap();
+
+        // Cut short after its header
+        let bytes = std::fs::read(&snapshot).unwrap();
+        std::fs::write(&snapshot, &bytes[..bytes.len() - 10]).unwrap();
+        let mut truncated = AmazonDataCleaner::new(&source);
+        assert!(truncated.load_cached(&snapshot).unwrap().is_some());
+        assert_eq!(truncated.data.len(), 3);
+        assert_eq!(std::fs::metadata(&snapshot).unwrap().len(), bytes.len() as u64);
+        assert!(!dir.join("meta.snapshot.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_changed_source_makes_the_snapshot_stale() {
+        let (dir, source, snapshot) = snapshot_files("snapshot-stale");
+        AmazonDataCleaner::new(&source).load_cached(&snapshot).unwrap();
+
+        std::fs::write(&source, SAMPLE_META.replace("Candlemas", "Candlemass")).unwrap();
+        let mut stale = AmazonDataCleaner::new(&source);
+        assert!(!stale.load_snapshot(&snapshot).unwrap());
+        assert!(stale.data.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
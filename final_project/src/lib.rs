@@ -0,0 +1,13 @@
+pub mod asin_index;
+pub mod category;
+pub mod cli;
+pub mod community;
+pub mod data_analysis;
+pub mod data_processing;
+pub mod output;
+pub mod product_graph;
+pub mod sampling;
+pub mod snapshot;
+pub mod statistics;
+#[cfg(test)]
+mod test_fixtures;
@@ -0,0 +1,73 @@
+use serde::Serialize;
+use std::error::Error;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    JsonLines,
+    Csv,
+}
+
+// Where a result came from; written with every record so published numbers can be regenerated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Provenance {
+    pub seed: u64,
+    pub sample_size: usize,
+}
+
+#[derive(Serialize)]
+struct JsonDocument<'a, T: Serialize + ?Sized> {
+    #[serde(flatten)]
+    provenance: Provenance,
+    results: &'a T,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a, T: Serialize> {
+    #[serde(flatten)]
+    provenance: Provenance,
+    #[serde(flatten)]
+    record: &'a T,
+}
+
+// A single JSON document `{"seed", "sample_size", "results"}`, for results that aren't a flat table
+pub fn write_json<T: Serialize + ?Sized>(
+    out: &mut dyn Write,
+    provenance: Provenance,
+    results: &T,
+) -> Result<(), Box<dyn Error>> {
+    let document = JsonDocument { provenance, results };
+    serde_json::to_writer_pretty(&mut *out, &document)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+// Write a table of flat records. JSON Lines and CSV repeat the seed and sample size on
+// every row so each row stands on its own.
+pub fn write_records<T: Serialize>(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    provenance: Provenance,
+    records: &[T],
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        OutputFormat::Text => return Err("text output is written by each command".into()),
+        OutputFormat::Json => write_json(out, provenance, records)?,
+        OutputFormat::JsonLines => {
+            for record in records {
+                serde_json::to_writer(&mut *out, &JsonLine { provenance, record })?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut *out);
+            for record in records {
+                writer.serialize((provenance, record))?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
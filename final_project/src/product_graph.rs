@@ -0,0 +1,151 @@
+use crate::data_processing::{Product, EXTERNAL_GROUP};
+use petgraph::graph::Graph;
+use petgraph::visit::EdgeRef;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+pub type ProductGraph = Graph<ProductNode, CoPurchaseEdge>;
+
+// Node payload: enough product metadata that analyses don't need the `Vec<Product>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProductNode {
+    pub id: u32,
+    pub asin: Option<String>,
+    pub group: String,
+    pub salesrank: Option<u32>,
+    pub avg_rating: Option<f32>,
+    pub review_count: Option<u32>,
+}
+
+impl ProductNode {
+    pub fn from_product(product: &Product) -> Self {
+        ProductNode {
+            id: product.id,
+            asin: product.asin.clone(),
+            group: product.group.clone().unwrap_or_else(|| "Unknown".to_string()),
+            // Ranks `clean_data` filled in are not real observations
+            salesrank: product.observed_salesrank(),
+            avg_rating: product.avg_rating,
+            review_count: product.total_reviews,
+        }
+    }
+
+    // Placeholder for a similar ASIN that is not in the loaded data. Give each
+    // placeholder its own `id` below `EXTERNAL_PRODUCT_ID` so outputs can tell them apart.
+    pub fn external(asin: &str, id: u32) -> Self {
+        ProductNode {
+            id,
+            asin: Some(asin.trim().to_string()),
+            group: EXTERNAL_GROUP.to_string(),
+            salesrank: None,
+            avg_rating: None,
+            review_count: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum EdgeDirection {
+    // The source lists the target in its `similar` field
+    #[default]
+    Forward,
+}
+
+// Edge payload for "source lists target as similar"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct CoPurchaseEdge {
+    pub direction: EdgeDirection,
+    // Position of the target in the source's `similar` list, 0 being the first
+    pub rank: usize,
+    // Whether the target also lists the source
+    pub reciprocated: bool,
+}
+
+impl CoPurchaseEdge {
+    pub fn forward(rank: usize) -> Self {
+        CoPurchaseEdge {
+            direction: EdgeDirection::Forward,
+            rank,
+            reciprocated: false,
+        }
+    }
+}
+
+// Set `reciprocated` on every edge whose reverse edge is also in the graph
+pub fn mark_reciprocated(graph: &mut ProductGraph) {
+    let edges: HashSet<(usize, usize)> = graph
+        .edge_references()
+        .map(|e| (e.source().index(), e.target().index()))
+        .collect();
+    for edge in graph.edge_indices() {
+        if let Some((source, target)) = graph.edge_endpoints(edge) {
+            graph[edge].reciprocated = edges.contains(&(target.index(), source.index()));
+        }
+    }
+}
+
+// Anything the analyses can read a product id and group from. Implemented for the
+// typed `ProductNode` and for the plain `(id, group)` tuples used by older code.
+pub trait GroupedNode {
+    fn product_id(&self) -> u32;
+    fn group(&self) -> &str;
+}
+
+impl GroupedNode for ProductNode {
+    fn product_id(&self) -> u32 {
+        self.id
+    }
+
+    fn group(&self) -> &str {
+        &self.group
+    }
+}
+
+impl GroupedNode for (u32, String) {
+    fn product_id(&self) -> u32 {
+        self.0
+    }
+
+    fn group(&self) -> &str {
+        &self.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_processing::AmazonDataCleaner;
+    use crate::test_fixtures::{chain_products, sample_cleaner};
+    use petgraph::graph::NodeIndex;
+
+    #[test]
+    fn test_nodes_carry_product_fields() {
+        let graph = sample_cleaner().create_global_graph();
+        let candlemas = &graph[NodeIndex::new(2)];
+        assert_eq!(candlemas.asin.as_deref(), Some("0738700797"));
+        assert_eq!(candlemas.salesrank, Some(168596));
+        assert_eq!(candlemas.avg_rating, Some(4.5));
+        assert_eq!(candlemas.review_count, Some(1));
+    }
+
+    #[test]
+    fn test_nodes_only_carry_observed_salesranks() {
+        let mut products = chain_products(2);
+        products[0].salesrank = Some(10);
+        let mut cleaner = AmazonDataCleaner::from_products(products);
+        cleaner.clean_data();
+        assert!(cleaner.data[1].salesrank_imputed);
+        let ranks: Vec<Option<u32>> = cleaner.create_global_graph().node_weights().map(|n| n.salesrank).collect();
+        assert_eq!(ranks, vec![Some(10), None]);
+    }
+
+    #[test]
+    fn test_edges_record_rank_and_reciprocity() {
+        // 1 lists 2 second, 2 lists 1 fifth, and both edges are reciprocated
+        let graph = sample_cleaner().create_global_graph();
+        let mut edges: Vec<CoPurchaseEdge> = graph.edge_weights().copied().collect();
+        edges.sort_by_key(|e| e.rank);
+        assert_eq!(edges.iter().map(|e| e.rank).collect::<Vec<_>>(), vec![1, 4]);
+        assert!(edges.iter().all(|e| e.reciprocated && e.direction == EdgeDirection::Forward));
+    }
+}
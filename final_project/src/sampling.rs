@@ -0,0 +1,485 @@
+use crate::asin_index::AsinIndex;
+use crate::data_processing::{GroupBy, Product};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// All sampling goes through ChaCha8 so a seed gives the same sample on every
+// platform. `rand` may change its shuffling and index sampling between releases,
+// so samples are only reproducible with the `rand` version locked in Cargo.lock
+pub type SampleRng = ChaCha8Rng;
+
+pub fn seeded_rng(seed: u64) -> SampleRng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+// A fresh seed for runs that don't pass one; print or store it to reproduce the run
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
+
+// Ways of picking a sample from the product graph. Every strategy except
+// `UniformNode` follows `similar` links, so more of the co-purchase edges survive
+// in the sampled graph.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SamplingStrategy {
+    // Products chosen uniformly at random
+    UniformNode { sample_size: usize },
+    // Edges chosen uniformly at random, keeping both endpoints; an edge whose new
+    // endpoints don't both fit is skipped. The graph builders then add every edge
+    // between the chosen products
+    InducedEdge { sample_size: usize },
+    // Breadth-first search from the given ASINs (or random products if empty)
+    Snowball { seeds: Vec<String>, sample_size: usize },
+    // Random walk that jumps back to its start with `restart_probability`, and to a
+    // new random start at products without similar links
+    RandomWalkWithRestart { sample_size: usize, restart_probability: f64 },
+    // Forest fire: each burning product ignites a geometric number of its
+    // unvisited similar products, with mean p / (1 - p); p must be in [0, 1)
+    ForestFire { sample_size: usize, forward_probability: f64 },
+}
+
+// A walk that finds no new product in this many steps starts again elsewhere
+const MAX_STALLED_STEPS: usize = 1000;
+
+impl SamplingStrategy {
+    pub fn sample_size(&self) -> usize {
+        match self {
+            SamplingStrategy::UniformNode { sample_size }
+            | SamplingStrategy::InducedEdge { sample_size }
+            | SamplingStrategy::Snowball { sample_size, .. }
+            | SamplingStrategy::RandomWalkWithRestart { sample_size, .. }
+            | SamplingStrategy::ForestFire { sample_size, .. } => *sample_size,
+        }
+    }
+
+    // Checks the probabilities; a forward probability of 1 would burn forever
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            SamplingStrategy::RandomWalkWithRestart { restart_probability, .. }
+                if !(0.0..=1.0).contains(restart_probability) =>
+            {
+                Err(format!("restart probability must be in [0, 1], got {}", restart_probability))
+            }
+            SamplingStrategy::ForestFire { forward_probability, .. } if !(0.0..1.0).contains(forward_probability) => {
+                Err(format!("forward probability must be in [0, 1), got {}", forward_probability))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Positions into `products` in the order they were sampled, or the `validate` error
+    pub fn sample_positions(&self, products: &[Product], index: &AsinIndex, seed: u64) -> Result<Vec<usize>, String> {
+        self.validate()?;
+        let mut rng = seeded_rng(seed);
+        let target = self.sample_size().min(products.len());
+        if target == 0 {
+            return Ok(Vec::new());
+        }
+
+        let positions = match self {
+            SamplingStrategy::UniformNode { .. } => {
+                rand::seq::index::sample(&mut rng, products.len(), target).into_vec()
+            }
+            SamplingStrategy::InducedEdge { .. } => {
+                let adjacency = similar_adjacency(products, index);
+                let mut edges: Vec<(usize, usize)> = adjacency
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(source, targets)| targets.iter().map(move |&t| (source, t)))
+                    .collect();
+                edges.shuffle(&mut rng);
+
+                let mut sample = SampleSet::new(products.len(), target);
+                for (source, target_node) in edges {
+                    let new_endpoints = usize::from(!sample.contains(source))
+                        + usize::from(source != target_node && !sample.contains(target_node));
+                    if new_endpoints > sample.remaining() {
+                        continue;
+                    }
+                    sample.insert(source);
+                    sample.insert(target_node);
+                    if sample.is_full() {
+                        break;
+                    }
+                }
+                // Too few edges: top up with uniformly chosen products
+                while !sample.is_full() {
+                    let position = sample.random_unvisited(&mut rng);
+                    sample.insert(position);
+                }
+                sample.into_positions()
+            }
+            SamplingStrategy::Snowball { seeds, .. } => {
+                let adjacency = similar_adjacency(products, index);
+                let mut sample = SampleSet::new(products.len(), target);
+                let mut queue: VecDeque<usize> = seeds.iter().filter_map(|asin| index.position(asin)).collect();
+
+                while !sample.is_full() {
+                    let position = match queue.pop_front() {
+                        Some(position) => position,
+                        None => sample.random_unvisited(&mut rng),
+                    };
+                    if sample.insert(position) {
+                        queue.extend(adjacency[position].iter().filter(|&&n| !sample.contains(n)));
+                    }
+                }
+                sample.into_positions()
+            }
+            SamplingStrategy::RandomWalkWithRestart { restart_probability, .. } => {
+                let adjacency = similar_adjacency(products, index);
+                let mut sample = SampleSet::new(products.len(), target);
+                let mut start = sample.random_unvisited(&mut rng);
+                let mut current = start;
+                let mut stalled = 0;
+                sample.insert(start);
+
+                while !sample.is_full() {
+                    let neighbours = &adjacency[current];
+                    // A dead end can't lead anywhere new, so start again elsewhere at once
+                    if stalled >= MAX_STALLED_STEPS || neighbours.is_empty() {
+                        start = sample.random_unvisited(&mut rng);
+                        current = start;
+                    } else if rng.gen::<f64>() < *restart_probability {
+                        current = start;
+                    } else {
+                        current = neighbours[rng.gen_range(0..neighbours.len())];
+                    }
+
+                    if sample.insert(current) {
+                        stalled = 0;
+                    } else {
+                        stalled += 1;
+                    }
+                }
+                sample.into_positions()
+            }
+            SamplingStrategy::ForestFire { forward_probability, .. } => {
+                let adjacency = similar_adjacency(products, index);
+                let mut sample = SampleSet::new(products.len(), target);
+                let mut queue = VecDeque::new();
+
+                while !sample.is_full() {
+                    let position = match queue.pop_front() {
+                        Some(position) => position,
+                        None => {
+                            let seed_position = sample.random_unvisited(&mut rng);
+                            sample.insert(seed_position);
+                            seed_position
+                        }
+                    };
+
+                    let unvisited: Vec<usize> = adjacency[position]
+                        .iter()
+                        .copied()
+                        .filter(|&n| !sample.contains(n))
+                        .collect();
+                    // Burning more than the unvisited neighbours changes nothing
+                    let mut burn_count = 0;
+                    while burn_count < unvisited.len() && rng.gen::<f64>() < *forward_probability {
+                        burn_count += 1;
+                    }
+                    for &neighbour in unvisited.choose_multiple(&mut rng, burn_count) {
+                        if sample.is_full() {
+                            break;
+                        }
+                        if sample.insert(neighbour) {
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+                sample.into_positions()
+            }
+        };
+        Ok(positions)
+    }
+
+    pub fn sample(&self, products: &[Product], index: &AsinIndex, seed: u64) -> Result<Vec<Product>, String> {
+        let positions = self.sample_positions(products, index, seed)?;
+        Ok(positions.into_iter().map(|position| products[position].clone()).collect())
+    }
+}
+
+// Out-neighbours of each product by position, following `similar` ASINs
+fn similar_adjacency(products: &[Product], index: &AsinIndex) -> Vec<Vec<usize>> {
+    products
+        .iter()
+        .map(|product| {
+            product
+                .similar
+                .iter()
+                .filter_map(|asin| index.position(asin))
+                .collect()
+        })
+        .collect()
+}
+
+// Sampled positions in visiting order, with constant-time membership checks
+struct SampleSet {
+    visited: Vec<bool>,
+    positions: Vec<usize>,
+    target: usize,
+}
+
+impl SampleSet {
+    fn new(population: usize, target: usize) -> Self {
+        SampleSet {
+            visited: vec![false; population],
+            positions: Vec::with_capacity(target),
+            target,
+        }
+    }
+
+    fn contains(&self, position: usize) -> bool {
+        self.visited[position]
+    }
+
+    fn insert(&mut self, position: usize) -> bool {
+        if self.visited[position] || self.is_full() {
+            return false;
+        }
+        self.visited[position] = true;
+        self.positions.push(position);
+        true
+    }
+
+    fn remaining(&self) -> usize {
+        self.target.saturating_sub(self.positions.len())
+    }
+
+    fn is_full(&self) -> bool {
+        self.positions.len() >= self.target
+    }
+
+    // Only called while the sample is not full, so an unvisited position exists
+    fn random_unvisited(&self, rng: &mut SampleRng) -> usize {
+        loop {
+            let position = rng.gen_range(0..self.visited.len());
+            if !self.visited[position] {
+                return position;
+            }
+        }
+    }
+
+    fn into_positions(self) -> Vec<usize> {
+        self.positions
+    }
+}
+
+// How the sample size is shared out between strata. Every stratum is capped at its
+// population size; the shortfall is not moved to other strata.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Allocation {
+    Proportional,
+    EqualPerStratum,
+    // Exact size per stratum; strata without a quota are not sampled
+    Quotas(HashMap<String, usize>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StratumWeight {
+    pub stratum: String,
+    pub population: usize,
+    pub sampled: usize,
+    // population / sampled: multiply a sampled product's contribution by this to
+    // estimate population totals; `None` when nothing was sampled
+    pub weight: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StratifiedSample {
+    pub seed: u64,
+    pub products: Vec<Product>,
+    pub strata: Vec<StratumWeight>,
+}
+
+impl StratifiedSample {
+    pub fn weight(&self, stratum: &str) -> Option<f64> {
+        self.strata.iter().find(|s| s.stratum == stratum).and_then(|s| s.weight)
+    }
+}
+
+pub fn stratified_sample(
+    products: &[Product],
+    stratify_by: GroupBy,
+    allocation: &Allocation,
+    sample_size: usize,
+    seed: u64,
+) -> StratifiedSample {
+    let mut rng = seeded_rng(seed);
+
+    // BTreeMap keeps the strata in a fixed order so the seed fully determines the sample
+    let mut strata: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (position, product) in products.iter().enumerate() {
+        strata.entry(stratify_by.stratum_of(product)).or_default().push(position);
+    }
+
+    let populations: Vec<usize> = strata.values().map(|p| p.len()).collect();
+    let sizes: Vec<usize> = match allocation {
+        Allocation::Proportional => proportional_sizes(&populations, sample_size.min(products.len())),
+        Allocation::EqualPerStratum => {
+            let count = populations.len().max(1);
+            (0..populations.len())
+                .map(|i| sample_size / count + usize::from(i < sample_size % count))
+                .collect()
+        }
+        Allocation::Quotas(quotas) => strata
+            .keys()
+            .map(|stratum| quotas.get(stratum).copied().unwrap_or(0))
+            .collect(),
+    };
+
+    let mut sampled_products = Vec::new();
+    let mut weights = Vec::new();
+    for ((stratum, positions), size) in strata.into_iter().zip(sizes) {
+        let chosen: Vec<usize> = positions.choose_multiple(&mut rng, size).copied().collect();
+        sampled_products.extend(chosen.iter().map(|&p| products[p].clone()));
+        weights.push(StratumWeight {
+            population: positions.len(),
+            sampled: chosen.len(),
+            weight: (!chosen.is_empty()).then(|| positions.len() as f64 / chosen.len() as f64),
+            stratum,
+        });
+    }
+
+    StratifiedSample {
+        seed,
+        products: sampled_products,
+        strata: weights,
+    }
+}
+
+// Largest-remainder rounding, so the sizes add up to exactly `sample_size`
+fn proportional_sizes(populations: &[usize], sample_size: usize) -> Vec<usize> {
+    let total: usize = populations.iter().sum();
+    if total == 0 {
+        return vec![0; populations.len()];
+    }
+
+    let exact: Vec<f64> = populations
+        .iter()
+        .map(|&p| p as f64 * sample_size as f64 / total as f64)
+        .collect();
+    let mut sizes: Vec<usize> = exact.iter().map(|e| e.floor() as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..populations.len()).collect();
+    by_remainder.sort_by(|&a, &b| {
+        let remainder = |i: usize| exact[i] - exact[i].floor();
+        remainder(b).total_cmp(&remainder(a)).then(a.cmp(&b))
+    });
+
+    let missing = sample_size - sizes.iter().sum::<usize>();
+    for &i in by_remainder.iter().take(missing) {
+        sizes[i] += 1;
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_processing::AmazonDataCleaner;
+    use crate::test_fixtures::{asin, chain_products};
+
+    fn sample_ids(products: &[Product], strategy: &SamplingStrategy, seed: u64) -> Result<Vec<u32>, String> {
+        let sample = strategy.sample(products, &AsinIndex::build(products), seed)?;
+        Ok(sample.iter().map(|p| p.id).collect())
+    }
+
+    #[test]
+    fn test_strategies_are_reproducible_without_repeats() {
+        let products = chain_products(200);
+        let strategies = vec![
+            SamplingStrategy::UniformNode { sample_size: 40 },
+            SamplingStrategy::InducedEdge { sample_size: 40 },
+            SamplingStrategy::Snowball { seeds: vec![], sample_size: 40 },
+            SamplingStrategy::RandomWalkWithRestart { sample_size: 40, restart_probability: 0.15 },
+            SamplingStrategy::ForestFire { sample_size: 40, forward_probability: 0.7 },
+        ];
+
+        for strategy in &strategies {
+            assert!(strategy.validate().is_ok());
+            let mut ids = sample_ids(&products, strategy, 11).unwrap();
+            assert_eq!(ids.len(), 40, "{:?}", strategy);
+            assert_eq!(ids, sample_ids(&products, strategy, 11).unwrap(), "{:?} is not reproducible", strategy);
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 40, "{:?} sampled a product twice", strategy);
+        }
+    }
+
+    #[test]
+    fn test_snowball_keeps_the_chain() {
+        // A snowball from one product keeps the chain, so nearly all edges survive
+        let products = chain_products(200);
+        let snowball = SamplingStrategy::Snowball { seeds: vec![asin(10)], sample_size: 5 };
+        let sample = snowball.sample(&products, &AsinIndex::build(&products), 0).unwrap();
+        assert_eq!(sample.iter().map(|p| p.id).collect::<Vec<_>>(), vec![10, 11, 12, 13, 14]);
+        let graph = AmazonDataCleaner::from_products(sample).create_global_graph();
+        assert_eq!(graph.edge_count(), 7);
+    }
+
+    #[test]
+    fn test_odd_induced_edge_sample_keeps_a_partner_for_every_product() {
+        let products = chain_products(200);
+        for seed in 0..20 {
+            let ids = sample_ids(&products, &SamplingStrategy::InducedEdge { sample_size: 5 }, seed).unwrap();
+            for &id in &ids {
+                assert!(ids.iter().any(|&other| other.abs_diff(id) == 1 || other.abs_diff(id) == 2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_walk_restarts_at_dead_ends() {
+        let mut isolated = chain_products(50);
+        isolated.iter_mut().for_each(|p| p.similar.clear());
+        let walk = SamplingStrategy::RandomWalkWithRestart { sample_size: 50, restart_probability: 0.15 };
+        assert_eq!(sample_ids(&isolated, &walk, 3).unwrap().len(), 50);
+    }
+
+    #[test]
+    fn test_forest_fire_needs_a_forward_probability_below_one() {
+        // A forward probability of 1 would never stop burning
+        for forward_probability in [1.0, -0.1, f64::NAN] {
+            let fire = SamplingStrategy::ForestFire { sample_size: 40, forward_probability };
+            assert!(fire.validate().is_err());
+            assert!(sample_ids(&chain_products(200), &fire, 11).is_err());
+        }
+    }
+
+    // chain_products(100) with the first ten moved to Toy: 45 Book, 45 Music, 10 Toy
+    fn toy_products() -> Vec<Product> {
+        let mut products = chain_products(100);
+        for product in products.iter_mut().take(10) {
+            product.group = Some("Toy".to_string());
+        }
+        products
+    }
+
+    #[test]
+    fn test_proportional_allocation_weights() {
+        let proportional = stratified_sample(&toy_products(), GroupBy::Group, &Allocation::Proportional, 20, 3);
+        assert_eq!(proportional.products.len(), 20);
+        let toys = proportional.strata.iter().find(|s| s.stratum == "Toy").unwrap();
+        assert_eq!((toys.population, toys.sampled), (10, 2));
+        assert_eq!(proportional.weight("Toy"), Some(5.0));
+    }
+
+    #[test]
+    fn test_equal_allocation_weights() {
+        let equal = stratified_sample(&toy_products(), GroupBy::Group, &Allocation::EqualPerStratum, 30, 3);
+        assert!(equal.strata.iter().all(|s| s.sampled == 10));
+        assert_eq!(equal.weight("Toy"), Some(1.0));
+    }
+
+    #[test]
+    fn test_quotas_only_sample_their_strata() {
+        let quotas = Allocation::Quotas([("Music".to_string(), 4)].into_iter().collect());
+        let music_only = stratified_sample(&toy_products(), GroupBy::Group, &quotas, 0, 3);
+        assert_eq!(music_only.products.len(), 4);
+        assert!(music_only.products.iter().all(|p| p.group.as_deref() == Some("Music")));
+        assert_eq!(music_only.weight("Book"), None);
+    }
+}
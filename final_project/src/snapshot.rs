@@ -0,0 +1,135 @@
+use crate::asin_index::AsinIndex;
+use crate::data_processing::Product;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"AMZSNAP\0";
+// Bump whenever `Product`, `AsinIndex` or the snapshot layout changes
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+// Identifies the exact source file a snapshot was built from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFingerprint {
+    pub size: u64,
+    pub modified_secs: u64,
+    pub modified_nanos: u32,
+    pub crc32: u32,
+}
+
+impl SourceFingerprint {
+    pub fn of(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut reader = BufReader::with_capacity(1 << 20, file);
+        let mut buffer = vec![0; 1 << 20];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(SourceFingerprint {
+            size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            crc32: hasher.finalize(),
+        })
+    }
+}
+
+pub struct Snapshot {
+    pub products: Vec<Product>,
+    pub asin_index: AsinIndex,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotHeader {
+    version: u32,
+    source: SourceFingerprint,
+}
+
+// Layout: magic bytes, then the bincode header, then the bincode products and index.
+// The header is read on its own so a stale snapshot is rejected without decoding the data.
+// The snapshot is written next to `path` and renamed into place, so an interrupted
+// write never leaves a truncated snapshot behind.
+pub fn write_snapshot(
+    path: impl AsRef<Path>,
+    source: &SourceFingerprint,
+    products: &[Product],
+    asin_index: &AsinIndex,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    let written = write_snapshot_file(Path::new(&temp_path), source, products, asin_index)
+        .and_then(|()| fs::rename(&temp_path, path).map_err(Into::into));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+fn write_snapshot_file(
+    path: &Path,
+    source: &SourceFingerprint,
+    products: &[Product],
+    asin_index: &AsinIndex,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(SNAPSHOT_MAGIC)?;
+    let header = SnapshotHeader {
+        version: SNAPSHOT_VERSION,
+        source: source.clone(),
+    };
+    bincode::serialize_into(&mut writer, &header)?;
+    bincode::serialize_into(&mut writer, products)?;
+    bincode::serialize_into(&mut writer, asin_index)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// `Ok(None)` when the snapshot is missing, from another version, built from a
+// different source file, or cut short
+pub fn read_snapshot(
+    path: impl AsRef<Path>,
+    source: &SourceFingerprint,
+) -> Result<Option<Snapshot>, Box<dyn Error>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0; 8];
+    if reader.read_exact(&mut magic).is_err() || &magic != SNAPSHOT_MAGIC {
+        return Ok(None);
+    }
+    let header: SnapshotHeader = match bincode::deserialize_from(&mut reader) {
+        Ok(header) => header,
+        Err(_) => return Ok(None),
+    };
+    if header.version != SNAPSHOT_VERSION || &header.source != source {
+        return Ok(None);
+    }
+
+    let body = bincode::deserialize_from(&mut reader)
+        .and_then(|products| Ok((products, bincode::deserialize_from(&mut reader)?)));
+    match body {
+        Ok((products, asin_index)) => Ok(Some(Snapshot { products, asin_index })),
+        Err(_) => Ok(None),
+    }
+}
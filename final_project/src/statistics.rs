@@ -0,0 +1,101 @@
+use serde::Serialize;
+
+// Summary statistics of one variable. Everything but the counts is `None` when no
+// values are present; `std_dev` is the sample standard deviation and needs two values.
+#[derive(Debug, Clone, PartialEq, Serialize, Default)]
+pub struct DescriptiveStats {
+    pub count: usize,
+    pub missing: usize,
+    pub mean: Option<f64>,
+    pub std_dev: Option<f64>,
+    pub min: Option<f64>,
+    pub q1: Option<f64>,
+    pub median: Option<f64>,
+    pub q3: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl DescriptiveStats {
+    pub fn from_values(mut values: Vec<f64>, missing: usize) -> Self {
+        let count = values.len();
+        if count == 0 {
+            return DescriptiveStats {
+                missing,
+                ..DescriptiveStats::default()
+            };
+        }
+
+        values.sort_by(f64::total_cmp);
+        let mean = values.iter().sum::<f64>() / count as f64;
+        let std_dev = (count > 1).then(|| {
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1) as f64;
+            variance.sqrt()
+        });
+
+        DescriptiveStats {
+            count,
+            missing,
+            mean: Some(mean),
+            std_dev,
+            min: values.first().copied(),
+            q1: Some(quantile(&values, 0.25)),
+            median: Some(quantile(&values, 0.5)),
+            q3: Some(quantile(&values, 0.75)),
+            max: values.last().copied(),
+        }
+    }
+
+    // Present values are `Some`, missing ones `None`
+    pub fn from_options<I>(values: I) -> Self
+    where
+        I: IntoIterator<Item = Option<f64>>,
+    {
+        let mut present = Vec::new();
+        let mut missing = 0;
+        for value in values {
+            match value {
+                Some(value) => present.push(value),
+                None => missing += 1,
+            }
+        }
+        Self::from_values(present, missing)
+    }
+}
+
+// Linear interpolation between closest ranks; `sorted` must be sorted and non-empty.
+// `q` is clamped to [0, 1].
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptive_stats_skip_missing_values() {
+        let stats = DescriptiveStats::from_options([Some(4.0), None, Some(1.0), Some(3.0), Some(2.0)]);
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.missing, 1);
+        assert_eq!(stats.mean, Some(2.5));
+        assert_eq!((stats.min, stats.max), (Some(1.0), Some(4.0)));
+        assert_eq!((stats.q1, stats.median, stats.q3), (Some(1.75), Some(2.5), Some(3.25)));
+        assert!((stats.std_dev.unwrap() - (5.0f64 / 3.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_descriptive_stats_of_nothing() {
+        let stats = DescriptiveStats::from_values(vec![], 2);
+        assert_eq!((stats.count, stats.missing), (0, 2));
+        assert_eq!(stats.mean, None);
+    }
+
+    #[test]
+    fn test_quantile_never_indexes_past_the_data() {
+        assert_eq!(quantile(&[1.0, 2.0, 3.0], 1.25), 3.0);
+        assert_eq!(quantile(&[1.0, 2.0, 3.0], -0.25), 1.0);
+    }
+}
@@ -0,0 +1,94 @@
+// Data shared by the `tests` modules
+use crate::data_processing::{AmazonDataCleaner, Product, ProductReader};
+use petgraph::graph::Graph;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+// A small excerpt in the SNAP amazon-meta.txt format
+pub const SAMPLE_META: &str = "# Full information about Amazon Share the Love products
+Total items: 3
+
+Id:   0
+ASIN: 0771044445
+  discontinued product
+
+Id:   1
+ASIN: 0827229534
+  title: Patterns of Preaching: A Sermon Sampler
+  group: Book
+  salesrank: 396585
+  similar: 2  0804215715  0738700797
+  categories: 2
+   |Books[283155]|Subjects[1000]|Religion & Spirituality[22]|Christianity[12290]|Clergy[12360]|Preaching[12368]
+   |Books[283155]|Subjects[1000]|Religion & Spirituality[22]|Christianity[12290]|Clergy[12360]|Sermons[12370]
+  reviews: total: 2  downloaded: 2  avg rating: 5
+    2000-7-28  cutomer: A2JW67OY8U6HHK  rating: 5  votes:  10  helpful:   9
+    2003-12-14  customer: A2VE83MZF98ITY  rating: 5  votes:   6  helpful:   5
+
+Id:   2
+ASIN: 0738700797
+  title: Candlemas: Feast of Flames
+  group: Book
+  salesrank: 168596
+  similar: 5  0738700827  1567184960  1567182836  0738700525  0827229534
+  categories: 1
+   |Books[283155]|Subjects[1000]|Religion & Spirituality[22]|Earth-Based Religions[12472]|Wicca[12484]
+  reviews: total: 1  downloaded: 1  avg rating: 4.5
+    2001-12-16  customer: A11NCO6YTE4BTJ  rating: 5  votes:   5  helpful:   4
+";
+
+// `SAMPLE_META` parsed, uncleaned
+pub fn sample_products() -> Vec<Product> {
+    ProductReader::new(Cursor::new(SAMPLE_META))
+        .collect::<Result<_, _>>()
+        .expect("sample parses")
+}
+
+pub fn sample_cleaner() -> AmazonDataCleaner {
+    AmazonDataCleaner::from_products(sample_products())
+}
+
+pub fn asin(id: u32) -> String {
+    format!("A{:09}", id)
+}
+
+// A product with only an id and its `asin`; tests fill in the rest with struct update syntax
+pub fn product(id: u32) -> Product {
+    Product {
+        id,
+        asin: Some(asin(id)),
+        ..Product::default()
+    }
+}
+
+// Products 0..n alternating Book and Music, where each lists the next two products as similar
+pub fn chain_products(n: u32) -> Vec<Product> {
+    (0..n)
+        .map(|id| Product {
+            group: Some(if id % 2 == 0 { "Book" } else { "Music" }.to_string()),
+            similar: vec![asin(id + 1), asin(id + 2)],
+            ..product(id)
+        })
+        .collect()
+}
+
+// A graph with node i labelled (i, groups[i]) and the given edges between positions
+pub fn group_graph(groups: &[&str], edges: &[(usize, usize)]) -> Graph<(u32, String), ()> {
+    let mut graph = Graph::new();
+    let nodes: Vec<_> = groups
+        .iter()
+        .enumerate()
+        .map(|(id, group)| graph.add_node((id as u32, group.to_string())))
+        .collect();
+    for &(source, target) in edges {
+        graph.add_edge(nodes[source], nodes[target], ());
+    }
+    graph
+}
+
+// A fresh directory under the system temp dir, unique to this test process and `name`
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("amazon-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}